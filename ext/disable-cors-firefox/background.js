@@ -0,0 +1,13 @@
+browser.webRequest.onHeadersReceived.addListener(
+    (details) => {
+        const responseHeaders = details.responseHeaders.filter(
+            (header) => header.name.toLowerCase() !== "access-control-allow-origin"
+        );
+
+        responseHeaders.push({ name: "Access-Control-Allow-Origin", value: "*" });
+
+        return { responseHeaders };
+    },
+    { urls: ["<all_urls>"] },
+    ["blocking", "responseHeaders"]
+);
@@ -1,19 +1,86 @@
 use std::{
+    collections::{HashMap, HashSet},
     fs::{self, File},
     io::{BufRead, BufReader, Write},
-    path::Path,
-    time::SystemTime,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
 
+use ::url::Url;
 use chrono::{DateTime, Utc};
-use clap::Parser;
-use tokio::sync::mpsc::channel;
+use clap::{Parser, Subcommand};
+use regex::Regex;
+use tokio::{sync::mpsc::channel, task::JoinHandle};
 
-use crate::{error::ScrapeResult, scraper::*, util::*};
+use crate::{
+    config::JobConfig,
+    error::{ScrapeError, ScrapeResult},
+    export::{
+        export_path, find_book_dirs, read_book, Book, BookPage, CbzExporter, ExportFormat,
+        Exporter, PackMode,
+    },
+    scraper::*,
+};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+// A rate of zero or less would turn the limit off instead of slowing requests down
+fn parse_rate(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!("{value} is not a positive number")),
+    }
+}
+
+// Resolves on Ctrl-C, or SIGTERM on unix. A signal that cannot be listened to never resolves
+async fn interrupted() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check the job and report its errors without scraping anything
+    Validate(Args),
+}
+
+#[derive(clap::Args, Debug)]
 struct Args {
+    /// Path to a TOML or YAML job file, flags override its values
+    #[arg(short, long)]
+    config: Option<String>,
+
     /// List of crawled urls
     #[arg(short = 'l', long)]
     urls: Vec<String>,
@@ -46,7 +113,81 @@ struct Args {
     #[arg(long)]
     image_height: Option<usize>,
 
-    /// MIME types of downloaded images
+    /// Max width of downloaded images
+    #[arg(long)]
+    image_max_width: Option<usize>,
+
+    /// Max height of downloaded images
+    #[arg(long)]
+    image_max_height: Option<usize>,
+
+    /// Min width/height ratio of downloaded images, e.g. 1:3 or 0.33
+    #[arg(long)]
+    image_min_ratio: Option<AspectRatio>,
+
+    /// Max width/height ratio of downloaded images, e.g. 3:1 to skip wide banners
+    #[arg(long)]
+    image_max_ratio: Option<AspectRatio>,
+
+    /// Min file size of downloaded images in bytes
+    #[arg(long)]
+    image_min_bytes: Option<usize>,
+
+    /// Max file size of downloaded images in bytes
+    #[arg(long)]
+    image_max_bytes: Option<usize>,
+
+    /// Only download images whose src matches this regex
+    #[arg(long)]
+    image_src_include: Option<Regex>,
+
+    /// Skip images whose src matches this regex
+    #[arg(long)]
+    image_src_exclude: Option<Regex>,
+
+    /// Only download images whose alt matches this regex
+    #[arg(long)]
+    image_alt_include: Option<Regex>,
+
+    /// Skip images whose alt matches this regex
+    #[arg(long)]
+    image_alt_exclude: Option<Regex>,
+
+    /// Only download images whose class attribute matches this regex
+    #[arg(long)]
+    image_class_include: Option<Regex>,
+
+    /// Skip images whose class attribute matches this regex
+    #[arg(long)]
+    image_class_exclude: Option<Regex>,
+
+    /// Directory of unwanted images (watermarks, ads), similar images are skipped
+    #[arg(long)]
+    blacklist_dir: Option<String>,
+
+    /// Skip images similar to one already downloaded in the run
+    #[arg(long)]
+    skip_similar: bool,
+
+    /// Perceptual hash comparing images: ahash, dhash
+    #[arg(long)]
+    phash: Option<HashAlgorithm>,
+
+    /// Max number of differing bits (out of 64) for two images to be similar
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..=64))]
+    phash_threshold: Option<u32>,
+
+    /// Size checked against the min width and height: natural (of the downloaded
+    /// image), rendered (of the element on the page, checked before downloading)
+    #[arg(long)]
+    image_size_source: Option<SizeSource>,
+
+    /// Keep images whose type or size cannot be read instead of skipping them
+    #[arg(long)]
+    allow_unknown_size: bool,
+
+    /// MIME types of downloaded images: image/jpeg, image/png, image/webp, image/gif,
+    /// image/avif, image/svg+xml, image/bmp, image/x-icon or image/* for all of them
     #[arg(long)]
     image_types: Vec<ImageMimeType>,
 
@@ -57,8 +198,147 @@ struct Args {
     /// Format of crawled urls
     #[arg(long)]
     url_regex: Option<String>,
+
+    /// Follow crawled urls up to the given depth
+    #[arg(short, long)]
+    depth: Option<usize>,
+
+    /// Skip pages and files completed by a previous run in the output folder
+    #[arg(long)]
+    resume: bool,
+
+    /// Engine loading the pages: browser, http (no JavaScript, faster)
+    #[arg(short, long)]
+    engine: Option<EngineKind>,
+
+    /// Max attempts to load a page or an image, including the first one
+    #[arg(long)]
+    max_attempts: Option<NonZeroUsize>,
+
+    /// Delay in milliseconds before the first retry, doubled after each attempt
+    #[arg(long)]
+    retry_delay: Option<u64>,
+
+    /// Max random delay in milliseconds added to each retry
+    #[arg(long)]
+    retry_jitter: Option<u64>,
+
+    /// Errors worth retrying: navigation, fetch, timeout, server-error (5xx and 429), challenge
+    #[arg(long)]
+    retry_on: Vec<RetryOn>,
+
+    /// Max requests per second sent to the same host
+    #[arg(long, value_parser = parse_rate)]
+    rate_limit: Option<f64>,
+
+    /// Max windows loading pages of the same host at the same time
+    #[arg(long)]
+    host_windows: Option<NonZeroUsize>,
+
+    /// Min delay in milliseconds between two page loads on the same host
+    #[arg(long)]
+    host_delay: Option<u64>,
+
+    /// Skip urls disallowed by robots.txt and honor its Crawl-delay
+    #[arg(long)]
+    respect_robots: bool,
+
+    /// User-agent token matched against robots.txt groups
+    #[arg(long)]
+    robots_agent: Option<String>,
+
+    /// Path to the driver binary started for the browser engine
+    #[arg(long, env = "SCRAPER_DRIVER_PATH")]
+    driver_path: Option<String>,
+
+    /// Port the started driver listens on
+    #[arg(long, env = "SCRAPER_DRIVER_PORT")]
+    driver_port: Option<u16>,
+
+    /// URL of a running WebDriver/Selenium server to use instead of starting a driver
+    #[arg(long, env = "SCRAPER_WEBDRIVER_URL")]
+    webdriver_url: Option<String>,
+
+    /// Browser driven by the browser engine: chrome, firefox
+    #[arg(short, long)]
+    browser: Option<BrowserKind>,
+
+    /// Run the browser without opening windows
+    #[arg(long)]
+    headless: bool,
+
+    /// Size of browser windows, e.g. 1280x800
+    #[arg(long)]
+    window_size: Option<WindowSize>,
+
+    /// User-agent sent by the browser and the HTTP engine
+    #[arg(long)]
+    user_agent: Option<String>,
+
+    /// Proxy server used by the browser and the HTTP engine, e.g. http://host:port
+    #[arg(long)]
+    proxy: Option<String>,
+
+    /// Browser profile directory, a profile can only be opened by one worker
+    #[arg(long)]
+    user_data_dir: Option<String>,
+
+    /// Extra argument passed to the browser, can be repeated
+    #[arg(long, allow_hyphen_values = true)]
+    browser_arg: Vec<String>,
+
+    /// Format of scraped url files: txt (bare urls), jsonl, csv (with source page,
+    /// tag, attribute, link text, depth and timestamp)
+    #[arg(long)]
+    url_format: Option<UrlOutputFormat>,
+
+    /// Convert downloaded images to: jpeg, png (SVG and AVIF images are kept as is)
+    #[arg(long)]
+    convert: Option<ConvertFormat>,
+
+    /// Quality of converted JPEG images, from 1 to 100
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+
+    /// Scale down images whose longest side is larger, in pixels
+    #[arg(long)]
+    max_dimension: Option<u32>,
+
+    /// Path of saved images in the output folder, e.g. {title}/{index:03}.{ext}
+    /// (placeholders: title, index, url_basename, hash, host, ext, timestamp)
+    #[arg(long)]
+    image_name: Option<ImageNameTemplate>,
+
+    /// Write the images of each page as: files, cbz (one archive per page), both
+    #[arg(long)]
+    pack: Option<PackMode>,
+
+    /// Add ComicInfo.xml metadata (title, url) to CBZ archives
+    #[arg(long)]
+    comic_info: bool,
+
+    /// Export each image folder of the output to: cbz, epub, pdf, can be repeated
+    #[arg(long)]
+    export: Vec<ExportFormat>,
+
+    /// Handle images with the same content as a saved one: skip, link (hard link)
+    #[arg(long)]
+    dedup: Option<DedupMode>,
+
+    /// File keeping the hashes of saved images to deduplicate across runs
+    #[arg(long)]
+    dedup_index: Option<String>,
+}
+
+// Settings of a run once the job file and the flags are merged
+struct Job {
+    urls: Vec<String>,
+    paths: Vec<String>,
+    strategy: ScrapeStrategy,
+    stages: Vec<Stage>,
 }
 
+#[derive(Default)]
 pub struct CommandLineInterface {
     //
 }
@@ -68,63 +348,336 @@ impl CommandLineInterface {
         Self {}
     }
 
+    // Seeds the work queue and returns how many workers are worth spawning
+    fn number_of_workers(&self, urls: &[String], context: &ScrapeContext, can_grow: bool) -> usize {
+        for url in urls {
+            context.queue().push(url.clone(), 0);
+        }
+
+        match can_grow {
+            // Newly discovered urls can keep every window busy
+            true => *context.strategy().number_of_windows(),
+            false => context
+                .queue()
+                .len()
+                .min(*context.strategy().number_of_windows()),
+        }
+    }
+
+    // Waits for the workers to stop and prints the urls that failed
+    async fn finish(
+        &self,
+        workers: Vec<JoinHandle<ScrapeResult<()>>>,
+        context: &ScrapeContext,
+    ) -> ScrapeResult<()> {
+        for worker in workers {
+            match worker.await {
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => println!("Worker stopped: {err}"),
+                Err(err) => println!("Worker crashed: {err}"),
+            }
+        }
+
+        let failures = context.take_failures();
+
+        if !failures.is_empty() {
+            println!("Failed to scrape {} url(s):", failures.len());
+
+            for (url, err) in failures {
+                println!("  - {url}: {err}");
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the file holding the content and true if the image has the same content
+    // as an already saved one
+    fn save_image(
+        &self,
+        image: &ScrapedImage,
+        strategy: &ScrapeStrategy,
+        index: &mut ContentIndex,
+        sanitizer: &mut PathSanitizer,
+    ) -> ScrapeResult<(PathBuf, bool)> {
+        let mode = match (strategy.dedup(), strategy.dedup_index()) {
+            (Some(mode), _) => *mode,
+            (None, Some(_)) => DedupMode::Skip,
+            (None, None) => {
+                return image
+                    .save(strategy.dest_dir(), strategy.image_name(), sanitizer)
+                    .map(|path| (path, false))
+            }
+        };
+        let hash = image.content_hash();
+
+        match (mode, index.get(&hash).cloned()) {
+            (DedupMode::Skip, Some(original)) => Ok((original, true)),
+            (DedupMode::Link, Some(original)) => image
+                .link(
+                    strategy.dest_dir(),
+                    strategy.image_name(),
+                    sanitizer,
+                    &original,
+                )
+                .map(|path| (path, true)),
+            _ => {
+                let path = image.save(strategy.dest_dir(), strategy.image_name(), sanitizer)?;
+
+                index.insert(hash, path.clone());
+
+                Ok((path, false))
+            }
+        }
+    }
+
+    // Writes the images of a finished page in DOM order into `<output>/<title>.cbz`.
+    // Pages sharing a title get a numeric suffix instead of overwriting each other,
+    // an archive with the same content is reused so that reruns keep their names
+    fn pack_page(
+        &self,
+        images: &[ScrapedImage],
+        strategy: &ScrapeStrategy,
+        sanitizer: &mut PathSanitizer,
+    ) -> ScrapeResult<PathBuf> {
+        let (title, url) = match images.first() {
+            Some(image) => (image.title().clone(), image.page_url().clone()),
+            None => return Ok(PathBuf::new()),
+        };
+        let mut ordered = images.iter().collect::<Vec<_>>();
+
+        ordered.sort_by_key(|image| *image.index());
+
+        let pages = ordered
+            .into_iter()
+            .map(|image| BookPage::new(image.extension(), image.content().clone()))
+            .collect();
+        let exporter = CbzExporter::new(*strategy.comic_info());
+        let stem = sanitizer.component("archives", &title);
+        let content = exporter.archive(&Book::new(title, Some(url), pages))?;
+        let dir = Path::new(strategy.dest_dir());
+        let mut path = dir.join(format!("{stem}.{}", exporter.extension()));
+        let mut suffix = 1;
+
+        fs::create_dir_all(dir).map_err(ScrapeError::IoError)?;
+
+        while path.exists() && !fs::read(&path).is_ok_and(|c| c == content) {
+            path = dir.join(format!("{stem}-{suffix}.{}", exporter.extension()));
+            suffix += 1;
+        }
+
+        fs::write(&path, content).map_err(ScrapeError::IoError)?;
+
+        Ok(path)
+    }
+
     async fn download_images(
         &self,
-        urls: &Vec<String>,
+        urls: &[String],
         strategy: ScrapeStrategy,
         filter: ScrapeImageFilter,
+        driver: Option<Arc<DriverService>>,
     ) -> ScrapeResult<()> {
         let (tx, mut rx) = channel(100);
+        let journal = Arc::new(CrawlJournal::open(strategy.dest_dir(), *strategy.resume())?);
+        let context = ScrapeContext::new(strategy.clone(), journal.clone(), driver)?;
+        let mut workers = vec![];
 
-        for i in 0..*strategy.number_of_windows() {
-            // Split urls to smaller batches
-            match get_batch_range(urls.len(), *strategy.number_of_windows(), i) {
-                Some((start, end)) => {
-                    let tx_clone = tx.clone();
-                    let filter_clone = filter.clone();
-                    let sub_urls = Vec::from(&urls[start..end]);
-
-                    tokio::spawn(async move {
-                        let mut scraper = ImageScraper::new(tx_clone, filter_clone);
-                        scraper.scrape(&sub_urls).await.unwrap();
-                    });
+        for _ in 0..self.number_of_workers(urls, &context, false) {
+            let tx_clone = tx.clone();
+            let filter_clone = filter.clone();
+            let context_clone = context.clone();
+
+            workers.push(tokio::spawn(async move {
+                ImageScraper::new(tx_clone, filter_clone, context_clone)
+                    .scrape()
+                    .await
+            }));
+        }
+
+        drop(tx);
+
+        // Pages with unsaved images must not be marked as visited
+        let mut incomplete_pages = HashSet::new();
+        let mut index = match strategy.dedup_index() {
+            Some(path) => ContentIndex::open(path)?,
+            None => ContentIndex::default(),
+        };
+        let mut deduplicated = 0;
+        // Hashes of the images kept so far, to skip the ones looking the same
+        let mut kept_hashes: Vec<PerceptualHash> = vec![];
+        let mut similar = 0;
+        let mut sanitizer = PathSanitizer::default();
+        let mut manifest = ImageManifest::open(strategy.dest_dir())?;
+
+        // Images waiting for their page to be done to be packed
+        let mut pending: HashMap<String, Vec<ScrapedImage>> = HashMap::new();
+
+        while let Some(message) = rx.recv().await {
+            match message {
+                ScrapeMessage::Data(image) => {
+                    let similarity = filter.similarity();
+
+                    if let (true, Some(hash)) = (similarity.skip_similar(), image.perceptual_hash())
+                    {
+                        if kept_hashes
+                            .iter()
+                            .any(|kept| similarity.is_similar(kept, hash))
+                        {
+                            similar += 1;
+                            journal.mark_saved(&image.journal_key());
+                            continue;
+                        }
+
+                        kept_hashes.push(*hash);
+                    }
+
+                    let saved = match strategy.pack().has_files() {
+                        true => self
+                            .save_image(&image, &strategy, &mut index, &mut sanitizer)
+                            .map(Some),
+                        false => Ok(None),
+                    };
+
+                    match saved {
+                        Ok(saved) => {
+                            if let Some((path, is_duplicate)) = saved {
+                                if is_duplicate {
+                                    deduplicated += 1;
+                                }
+
+                                let entry = ManifestEntry::new(&image, &path, is_duplicate);
+
+                                if let Err(err) = manifest.write(&entry) {
+                                    context.report_failure(image.src(), err);
+                                }
+                            }
+
+                            match strategy.pack().has_archive() {
+                                // Marked as saved once the archive is written
+                                true => pending
+                                    .entry(image.page_url().clone())
+                                    .or_default()
+                                    .push(image),
+                                false => journal.mark_saved(&image.journal_key()),
+                            }
+                        }
+                        Err(err) => {
+                            incomplete_pages.insert(image.page_url().clone());
+                            context.report_failure(image.src(), err);
+                        }
+                    }
+                }
+                ScrapeMessage::PageDone(url) => {
+                    let images = pending.remove(&url).unwrap_or_default();
+
+                    if incomplete_pages.contains(&url) {
+                        continue;
+                    }
+
+                    if strategy.pack().has_archive() {
+                        let keys = images
+                            .iter()
+                            .map(|image| image.journal_key())
+                            .collect::<Vec<_>>();
+
+                        let path = match self.pack_page(&images, &strategy, &mut sanitizer) {
+                            Ok(path) => path,
+                            Err(err) => {
+                                context.report_failure(&url, err);
+                                continue;
+                            }
+                        };
+
+                        // Files already have their record when both are written
+                        if !strategy.pack().has_files() {
+                            for image in &images {
+                                let entry = ManifestEntry::new(image, &path, false);
+
+                                if let Err(err) = manifest.write(&entry) {
+                                    context.report_failure(image.src(), err);
+                                }
+                            }
+                        }
+
+                        for key in keys {
+                            journal.mark_saved(&key);
+                        }
+                    }
+
+                    journal.mark_visited(&url);
                 }
-                None => break,
             }
         }
 
-        drop(tx);
+        if strategy.dedup().is_some() || strategy.dedup_index().is_some() {
+            println!("Deduplicated {deduplicated} image(s)");
+        }
+
+        if *filter.similarity().skip_similar() {
+            println!("Skipped {similar} similar image(s)");
+        }
 
-        while let Some(data) = rx.recv().await {
-            data.save(strategy.dest_dir()).unwrap();
+        self.finish(workers, &context).await?;
+
+        // Every image of the output is decoded, which would stall the runtime
+        if !strategy.exports().is_empty() {
+            tokio::task::spawn_blocking(move || Self::export_books(&strategy))
+                .await
+                .map_err(|err| ScrapeError::IoError(std::io::Error::other(err)))?;
         }
 
         Ok(())
     }
 
+    // Exports the image folders of the output, a failed book does not stop the others
+    fn export_books(strategy: &ScrapeStrategy) {
+        let root = Path::new(strategy.dest_dir());
+
+        for format in strategy.exports() {
+            let exporter = format.exporter(*strategy.comic_info());
+
+            for dir in find_book_dirs(root) {
+                let path = export_path(root, &dir, exporter.extension());
+                let result = read_book(&dir).and_then(|book| match book {
+                    Some(book) => exporter.export(&book, &path).map(|_| true),
+                    None => Ok(false),
+                });
+
+                match result {
+                    Ok(true) => println!("Exported {}", path.display()),
+                    Ok(false) => {}
+                    Err(err) => println!("Failed to export {}: {err}", dir.display()),
+                }
+            }
+        }
+    }
+
     async fn scrape_urls(
         &self,
-        urls: &Vec<String>,
+        urls: &[String],
         strategy: ScrapeStrategy,
         filter: ScrapeUrlFilter,
-    ) -> ScrapeResult<()> {
+        driver: Option<Arc<DriverService>>,
+    ) -> ScrapeResult<Vec<String>> {
         let (tx, mut rx) = channel(100);
+        let journal = Arc::new(match strategy.dest_dir().is_empty() {
+            true => CrawlJournal::default(),
+            false => CrawlJournal::open(strategy.dest_dir(), *strategy.resume())?,
+        });
+        let context = ScrapeContext::new(strategy.clone(), journal.clone(), driver)?;
+        let mut workers = vec![];
 
-        for i in 0..*strategy.number_of_windows() {
-            // Split urls to smaller batches
-            match get_batch_range(urls.len(), *strategy.number_of_windows(), i) {
-                Some((start, end)) => {
-                    let tx_clone = tx.clone();
-                    let filter_clone = filter.clone();
-                    let sub_urls = Vec::from(&urls[start..end]);
-
-                    tokio::spawn(async move {
-                        let mut scraper = UrlScraper::new(tx_clone, filter_clone);
-                        scraper.scrape(&sub_urls).await.unwrap();
-                    });
-                }
-                None => break,
-            }
+        for _ in 0..self.number_of_workers(urls, &context, *strategy.depth() > 0) {
+            let tx_clone = tx.clone();
+            let filter_clone = filter.clone();
+            let context_clone = context.clone();
+
+            workers.push(tokio::spawn(async move {
+                UrlScraper::new(tx_clone, filter_clone, context_clone)
+                    .scrape()
+                    .await
+            }));
         }
 
         drop(tx);
@@ -132,116 +685,621 @@ impl CommandLineInterface {
         let mut file: Option<File> = None;
         let now: DateTime<Utc> = SystemTime::now().into();
         let name = now.timestamp_millis();
+        let format = *strategy.url_format();
 
         if !strategy.dest_dir().is_empty() {
-            fs::create_dir_all(format!("{}", strategy.dest_dir())).unwrap();
-            file = Some(
-                fs::OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .append(true)
-                    .open(format!("{}/{}.txt", strategy.dest_dir(), name))
-                    .unwrap(),
-            );
+            fs::create_dir_all(strategy.dest_dir()).map_err(ScrapeError::IoError)?;
+
+            let mut f = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(format!(
+                    "{}/{}.{}",
+                    strategy.dest_dir(),
+                    name,
+                    format.extension()
+                ))
+                .map_err(ScrapeError::IoError)?;
+
+            if let Some(header) = format.header() {
+                writeln!(f, "{header}").map_err(ScrapeError::IoError)?;
+            }
+
+            file = Some(f);
+        } else if let Some(header) = format.header() {
+            println!("{header}");
         }
 
-        while let Some(data) = rx.recv().await {
-            match file.as_mut() {
-                Some(f) => {
-                    if writeln!(f, "{}", data).is_err() {
-                        println!(
-                            "Failed to  write url in file: {}/{}.txt",
-                            strategy.dest_dir(),
-                            name,
-                        );
-                    }
+        // Urls saved by a resumed run are not sent again but still feed the next stage
+        let mut scraped = journal.saved_keys();
+        let mut seen = scraped.iter().cloned().collect::<HashSet<_>>();
+
+        while let Some(message) = rx.recv().await {
+            let data = match message {
+                ScrapeMessage::Data(data) => data,
+                ScrapeMessage::PageDone(url) => {
+                    journal.mark_visited(&url);
+                    continue;
                 }
-                None => println!("Url: {}", data),
+            };
+
+            let url = data.url();
+
+            if seen.insert(url.clone()) {
+                scraped.push(url.clone());
+            }
+
+            match (file.as_mut(), format) {
+                (Some(f), _) => match writeln!(f, "{}", data.format(format)) {
+                    Ok(_) => journal.mark_saved(url),
+                    Err(err) => context.report_failure(url, ScrapeError::IoError(err)),
+                },
+                (None, UrlOutputFormat::Txt) => println!("Url: {}", url),
+                (None, _) => println!("{}", data.format(format)),
             }
         }
 
-        Ok(())
+        self.finish(workers, &context).await?;
+
+        Ok(scraped)
     }
 
-    fn read_urls_from_paths(&self, urls: &mut Vec<String>, paths: &Vec<String>) {
+    fn read_urls_from_paths(&self, urls: &mut Vec<String>, paths: &[String]) {
         for p in paths {
             let path = Path::new(&p);
 
             if path.is_file() {
                 let f = File::open(path.to_str().unwrap())
-                    .expect(&format!("Unable to open file: {}", p));
+                    .unwrap_or_else(|_| panic!("Unable to open file: {}", p));
+                let format = UrlOutputFormat::from_path(path);
                 let reader = BufReader::new(f).lines();
 
-                for line in reader {
-                    if let Ok(line) = line {
-                        urls.push(line);
-                    }
-                }
+                urls.extend(
+                    reader
+                        .map_while(Result::ok)
+                        .filter_map(|line| format.parse_url(&line)),
+                );
             } else if path.is_dir() {
-                for entry in path.read_dir().unwrap() {
-                    if let Ok(entry) = entry {
-                        let f = File::open(entry.path().to_str().unwrap()).expect(&format!(
+                for entry in path.read_dir().unwrap().flatten() {
+                    // Skip hidden files such as the crawl journal
+                    if entry.file_name().to_string_lossy().starts_with('.') {
+                        continue;
+                    }
+
+                    let f = File::open(entry.path().to_str().unwrap()).unwrap_or_else(|_| {
+                        panic!(
                             "Unable to open file: {}",
                             entry.file_name().to_str().unwrap_or("unknown :D")
-                        ));
-                        let reader = BufReader::new(f).lines();
+                        )
+                    });
+                    let format = UrlOutputFormat::from_path(&entry.path());
+                    let reader = BufReader::new(f).lines();
 
-                        for line in reader {
-                            if let Ok(line) = line {
-                                urls.push(line);
-                            }
-                        }
-                    }
+                    urls.extend(
+                        reader
+                            .map_while(Result::ok)
+                            .filter_map(|line| format.parse_url(&line)),
+                    );
                 }
             }
         }
     }
 
-    pub async fn run(&self) -> ScrapeResult<()> {
-        let args = Args::parse();
-        let mut urls = Vec::from(args.urls);
-        let mut strategy = ScrapeStrategy::default();
+    fn build_job(&self, args: Args) -> ScrapeResult<Job> {
+        let config = match &args.config {
+            Some(path) => JobConfig::load(path)?,
+            None => JobConfig::default(),
+        };
+        let mut urls = match args.urls.is_empty() {
+            true => config.urls().clone(),
+            false => args.urls,
+        };
+        let paths = match args.paths.is_empty() {
+            true => config.paths().clone(),
+            false => args.paths,
+        };
+        let mut strategy = config.strategy()?;
+
+        self.read_urls_from_paths(&mut urls, &paths);
+
+        if let Some(worker) = args.worker {
+            strategy.set_number_of_windows(worker);
+        }
+
+        if let Some(output) = args.output {
+            strategy.set_destination(output);
+        }
+
+        if let Some(depth) = args.depth {
+            strategy.set_depth(depth);
+        }
+
+        if args.resume {
+            strategy.set_resume(true);
+        }
+
+        if let Some(engine) = args.engine {
+            strategy.set_engine(engine);
+        }
+
+        let mut retry_policy = strategy.retry_policy().clone();
+
+        if let Some(max_attempts) = args.max_attempts {
+            retry_policy.set_max_attempts(max_attempts.get());
+        }
+
+        if let Some(retry_delay) = args.retry_delay {
+            retry_policy.set_base_delay(Duration::from_millis(retry_delay));
+        }
+
+        if let Some(retry_jitter) = args.retry_jitter {
+            retry_policy.set_jitter(Duration::from_millis(retry_jitter));
+        }
+
+        if !args.retry_on.is_empty() {
+            retry_policy.replace_retry_on(args.retry_on);
+        }
+
+        strategy.set_retry_policy(retry_policy);
+
+        let mut rate_limit = strategy.rate_limit().clone();
+
+        if let Some(requests) = args.rate_limit {
+            rate_limit.set_requests_per_second(requests);
+        }
 
-        self.read_urls_from_paths(&mut urls, &args.paths);
+        if let Some(windows) = args.host_windows {
+            rate_limit.set_max_windows_per_host(windows.get());
+        }
+
+        if let Some(delay) = args.host_delay {
+            rate_limit.set_min_delay(Duration::from_millis(delay));
+        }
+
+        strategy.set_rate_limit(rate_limit);
+
+        if args.respect_robots {
+            strategy.set_respect_robots(true);
+        }
+
+        if let Some(robots_agent) = args.robots_agent {
+            strategy.set_robots_user_agent(robots_agent);
+        }
+
+        let mut driver_config = strategy.driver().clone();
+
+        if let Some(driver_path) = args.driver_path {
+            driver_config.set_binary(driver_path);
+        }
+
+        if let Some(driver_port) = args.driver_port {
+            driver_config.set_port(driver_port);
+        }
+
+        if let Some(webdriver_url) = args.webdriver_url {
+            driver_config.set_url(webdriver_url);
+        }
+
+        strategy.set_driver(driver_config);
+
+        let mut browser_options = strategy.browser().clone();
+
+        if let Some(browser) = args.browser {
+            browser_options.set_kind(browser);
+        }
+
+        if args.headless {
+            browser_options.set_headless(true);
+        }
+
+        if let Some(window_size) = args.window_size {
+            browser_options.set_window_size(window_size);
+        }
+
+        if let Some(user_agent) = args.user_agent {
+            browser_options.set_user_agent(user_agent);
+        }
+
+        if let Some(proxy) = args.proxy {
+            browser_options.set_proxy(proxy);
+        }
+
+        if let Some(user_data_dir) = args.user_data_dir {
+            browser_options.set_user_data_dir(user_data_dir);
+        }
+
+        for arg in args.browser_arg {
+            browser_options.add_arg(arg);
+        }
+
+        strategy.set_browser_options(browser_options);
+
+        if let Some(url_format) = args.url_format {
+            strategy.set_url_format(url_format);
+        }
+
+        let mut conversion = strategy.conversion().clone();
+
+        if let Some(format) = args.convert {
+            conversion.set_format(format);
+        }
+
+        if let Some(quality) = args.quality {
+            conversion.set_quality(quality);
+        }
+
+        if let Some(max_dimension) = args.max_dimension {
+            conversion.set_max_dimension(max_dimension);
+        }
+
+        strategy.set_conversion(conversion);
+
+        if let Some(image_name) = args.image_name {
+            strategy.set_image_name(image_name);
+        }
+
+        if let Some(pack) = args.pack {
+            strategy.set_pack(pack);
+        }
+
+        if args.comic_info {
+            strategy.set_comic_info(true);
+        }
+
+        if !args.export.is_empty() {
+            strategy.replace_exports(args.export);
+        }
 
-        if args.worker.is_some() {
-            strategy.set_number_of_windows(args.worker.unwrap());
+        if let Some(dedup) = args.dedup {
+            strategy.set_dedup(dedup);
         }
 
-        if args.output.is_some() {
-            strategy.set_destination(args.output.unwrap());
+        if let Some(dedup_index) = args.dedup_index {
+            strategy.set_dedup_index(dedup_index);
         }
 
-        if args.url_scrape {
-            let mut filter = ScrapeUrlFilter::default();
+        let mut url_filter = config.url_filter()?;
 
-            if Vec::len(&args.url_tags) > 0 {
-                filter.replace_tags(args.url_tags);
+        if !args.url_tags.is_empty() {
+            url_filter.replace_tags(args.url_tags);
+        }
+
+        if let Some(url_regex) = args.url_regex {
+            Regex::new(&url_regex).map_err(|err| {
+                ScrapeError::ConfigError(format!("--url-regex \"{url_regex}\": {err}"))
+            })?;
+            url_filter.set_regex(url_regex);
+        }
+
+        let mut image_filter = config.image_filter()?;
+
+        if !args.image_types.is_empty() {
+            image_filter.replace_mime_types(args.image_types);
+        }
+
+        if let Some(image_width) = args.image_width {
+            image_filter.set_min_width(image_width);
+        }
+
+        if let Some(image_height) = args.image_height {
+            image_filter.set_min_height(image_height);
+        }
+
+        if let Some(size_source) = args.image_size_source {
+            image_filter.set_size_source(size_source);
+        }
+
+        if args.allow_unknown_size {
+            image_filter.set_allow_unknown_size(true);
+        }
+
+        if let Some(width) = args.image_max_width {
+            image_filter.set_max_width(width);
+        }
+
+        if let Some(height) = args.image_max_height {
+            image_filter.set_max_height(height);
+        }
+
+        if let Some(ratio) = args.image_min_ratio {
+            image_filter.set_min_aspect_ratio(ratio);
+        }
+
+        if let Some(ratio) = args.image_max_ratio {
+            image_filter.set_max_aspect_ratio(ratio);
+        }
+
+        if let Some(bytes) = args.image_min_bytes {
+            image_filter.set_min_bytes(bytes);
+        }
+
+        if let Some(bytes) = args.image_max_bytes {
+            image_filter.set_max_bytes(bytes);
+        }
+
+        let patterns = |base: &PatternFilter, include: Option<Regex>, exclude: Option<Regex>| {
+            let mut patterns = base.clone();
+
+            if let Some(include) = include {
+                patterns.set_include(include);
             }
 
-            if args.url_regex.is_some() {
-                filter.set_regex(args.url_regex.unwrap());
+            if let Some(exclude) = exclude {
+                patterns.set_exclude(exclude);
             }
 
-            self.scrape_urls(&urls, strategy, filter).await?;
+            patterns
+        };
+        let src = patterns(
+            image_filter.src_filter(),
+            args.image_src_include,
+            args.image_src_exclude,
+        );
+        let alt = patterns(
+            image_filter.alt_filter(),
+            args.image_alt_include,
+            args.image_alt_exclude,
+        );
+        let class = patterns(
+            image_filter.class_filter(),
+            args.image_class_include,
+            args.image_class_exclude,
+        );
+
+        image_filter.set_src_filter(src);
+        image_filter.set_alt_filter(alt);
+        image_filter.set_class_filter(class);
+
+        let mut similarity = image_filter.similarity().clone();
+
+        if let Some(algorithm) = args.phash {
+            similarity.set_algorithm(algorithm);
+        }
+
+        if let Some(threshold) = args.phash_threshold {
+            similarity.set_threshold(threshold);
+        }
+
+        if args.skip_similar {
+            similarity.set_skip_similar(true);
+        }
+
+        if let Some(dir) = &args.blacklist_dir {
+            similarity.set_blacklist(ImageBlacklist::load(dir)?);
+        }
+
+        image_filter.set_similarity(similarity);
+
+        // Flags select a single stage, the job file may define a whole pipeline
+        let kind = if args.url_scrape {
+            Some(StageKind::Urls)
         } else if args.image_download {
-            let mut filter = ScrapeImageFilter::default();
+            Some(StageKind::Images)
+        } else if !config.stages().is_empty() {
+            None
+        } else if *config.url_scrape() {
+            Some(StageKind::Urls)
+        } else if *config.image_download() {
+            Some(StageKind::Images)
+        } else {
+            return Ok(Job {
+                urls,
+                paths,
+                strategy,
+                stages: vec![],
+            });
+        };
+        let stages = match kind {
+            Some(kind) => {
+                let mut stage = Stage::new(kind, strategy.clone());
+
+                stage
+                    .set_url_filter(url_filter)
+                    .set_image_filter(image_filter);
+
+                vec![stage]
+            }
+            None => config.pipeline(&strategy, &url_filter, &image_filter)?,
+        };
+
+        Ok(Job {
+            urls,
+            paths,
+            strategy,
+            stages,
+        })
+    }
+
+    fn is_valid_url(url: &str) -> bool {
+        Url::parse(url).is_ok_and(|parsed| ["http", "https"].contains(&parsed.scheme()))
+    }
+
+    // Problems that would only show up once the browser is started
+    fn check(&self, job: &Job) -> Vec<String> {
+        let mut problems = vec![];
+
+        if job.stages.is_empty() {
+            problems.push(String::from(
+                "Nothing to do, enable url-scrape or image-download or define stages",
+            ));
+        }
 
-            if Vec::len(&args.image_types) > 0 {
-                filter.replace_mime_types(args.image_types);
+        let mut outputs: HashMap<&String, &String> = HashMap::new();
+
+        for (index, stage) in job.stages.iter().enumerate() {
+            // Images are not urls, nothing could be fed to the next stage
+            if *stage.kind() == StageKind::Images && index + 1 < job.stages.len() {
+                problems.push(format!(
+                    "Stage {} downloads images, only the last stage can",
+                    stage.name()
+                ));
+            }
+
+            if stage.strategy().browser().user_data_dir().is_some()
+                && *stage.strategy().number_of_windows() > 1
+            {
+                problems.push(format!(
+                    "Stage {}: a browser profile can only be opened by one worker",
+                    stage.name()
+                ));
             }
 
-            if args.image_width.is_some() {
-                filter.set_min_width(args.image_width.unwrap());
+            let output = stage.strategy().dest_dir();
+
+            // Each stage keeps its own journal in its output folder
+            if output.is_empty() {
+                continue;
             }
 
-            if args.image_height.is_some() {
-                filter.set_min_height(args.image_height.unwrap());
+            if !stage.strategy().exports().is_empty() && !stage.strategy().pack().has_files() {
+                problems.push(format!(
+                    "Stage {}: exports are made from image files, pack files or both",
+                    stage.name()
+                ));
+            }
+
+            if let Some(other) = outputs.insert(output, stage.name()) {
+                problems.push(format!(
+                    "Stages {other} and {} share the output folder {output}",
+                    stage.name()
+                ));
+            }
+        }
+
+        for path in &job.paths {
+            if !Path::new(path).exists() {
+                problems.push(format!("Path not found: {path}"));
+            }
+        }
+
+        if job.urls.is_empty() {
+            problems.push(String::from("No url to scrape"));
+        }
+
+        for url in job.urls.iter().filter(|url| !Self::is_valid_url(url)) {
+            problems.push(format!("Invalid url: {url}"));
+        }
+
+        let strategy = &job.strategy;
+        let kind = *strategy.browser().kind();
+
+        if *strategy.engine() == EngineKind::Browser && strategy.driver().url().is_none() {
+            match strategy.driver().find_binary(kind) {
+                Ok(Some(_)) => {}
+                Ok(None) => problems.push(format!(
+                    "Driver not found: {}",
+                    strategy.driver().resolve_binary(kind).unwrap_or_default()
+                )),
+                Err(err) => problems.push(err.to_string()),
+            }
+
+            if !Path::new(kind.extension()).is_dir() {
+                problems.push(format!("Extension not found: {}", kind.extension()));
+            }
+        }
+
+        problems
+    }
+
+    // Runs the stages in order, the urls scraped by a stage are the input of the next one
+    async fn run_stages(
+        &self,
+        stages: Vec<Stage>,
+        mut urls: Vec<String>,
+        driver: Option<Arc<DriverService>>,
+    ) -> ScrapeResult<()> {
+        let is_pipeline = stages.len() > 1;
+
+        for stage in stages {
+            if is_pipeline {
+                println!("Running stage {} on {} url(s)", stage.name(), urls.len());
             }
 
-            self.download_images(&urls, strategy, filter).await?;
+            match stage.kind() {
+                StageKind::Urls => {
+                    urls = self
+                        .scrape_urls(
+                            &urls,
+                            stage.strategy().clone(),
+                            stage.url_filter().clone(),
+                            driver.clone(),
+                        )
+                        .await?;
+                }
+                StageKind::Images => {
+                    self.download_images(
+                        &urls,
+                        stage.strategy().clone(),
+                        stage.image_filter().clone(),
+                        driver.clone(),
+                    )
+                    .await?;
+                }
+            }
         }
 
         Ok(())
     }
+
+    pub async fn run(&self) -> ScrapeResult<()> {
+        let cli = Cli::parse();
+        let (args, is_validating) = match cli.command {
+            Some(Command::Validate(args)) => (args, true),
+            None => (cli.args, false),
+        };
+        let mut job = self.build_job(args)?;
+        let problems = self.check(&job);
+
+        if is_validating {
+            if !problems.is_empty() {
+                println!("Found {} problem(s) in the job:", problems.len());
+
+                for problem in &problems {
+                    println!("  - {problem}");
+                }
+
+                return Err(ScrapeError::ConfigError(format!(
+                    "{} problem(s) found",
+                    problems.len()
+                )));
+            }
+
+            println!(
+                "Job is valid, {} url(s) to scrape in {} stage(s)",
+                job.urls.len(),
+                job.stages.len()
+            );
+
+            return Ok(());
+        }
+
+        // A normal run goes on with what it can, failing parts report their own errors
+        for problem in &problems {
+            println!("Warning: {problem}");
+        }
+
+        job.urls.retain(|url| Self::is_valid_url(url));
+
+        // A single driver is shared by every worker and stopped once the run is over
+        let driver = match job.strategy.engine() {
+            EngineKind::Browser => Some(Arc::new(
+                DriverService::start(job.strategy.driver(), *job.strategy.browser().kind()).await?,
+            )),
+            _ => None,
+        };
+        // Signals do not unwind the workers, so the driver is stopped here on both paths
+        let result = tokio::select! {
+            result = self.run_stages(job.stages, job.urls, driver.clone()) => result,
+            _ = interrupted() => {
+                println!("Interrupted, stopping");
+                Err(ScrapeError::Interrupted)
+            }
+        };
+
+        if let Some(driver) = driver {
+            driver.stop();
+        }
+
+        result
+    }
 }
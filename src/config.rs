@@ -0,0 +1,692 @@
+use std::{fmt::Display, fs, path::Path, str::FromStr, time::Duration};
+
+use derive_getters::Getters;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{
+    error::{ScrapeError, ScrapeResult},
+    scraper::*,
+};
+
+// Top-level keys are the kebab-case flag names, e.g. `image-download` for `--image-download`
+// or `workers` for `--worker`. Related settings are grouped in tables: [retry], [rate-limit],
+// [convert], [robots], [driver], [browser], [image] and [url], e.g. `retry.delay` for
+// `--retry-delay` or `image.width` for `--image-width`
+#[derive(Deserialize, Getters, Default, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct JobConfig {
+    urls: Vec<String>,
+    paths: Vec<String>,
+    url_scrape: bool,
+    image_download: bool,
+    workers: Option<usize>,
+    output: Option<String>,
+    depth: Option<usize>,
+    resume: bool,
+    engine: Option<String>,
+    url_format: Option<String>,
+    image_name: Option<String>,
+    pack: Option<String>,
+    comic_info: bool,
+    export: Vec<String>,
+    dedup: Option<String>,
+    dedup_index: Option<String>,
+    retry: RetryConfig,
+    rate_limit: RateLimitConfig,
+    convert: ConvertConfig,
+    robots: RobotsConfig,
+    driver: DriverSection,
+    browser: BrowserSection,
+    image: ImageFilterConfig,
+    url: UrlFilterConfig,
+    stages: Vec<StageConfig>,
+}
+
+#[derive(Deserialize, Getters, Default, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RetryConfig {
+    attempts: Option<usize>,
+    // Milliseconds
+    delay: Option<u64>,
+    jitter: Option<u64>,
+    on: Vec<String>,
+}
+
+#[derive(Deserialize, Getters, Default, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RateLimitConfig {
+    requests_per_second: Option<f64>,
+    host_windows: Option<usize>,
+    // Milliseconds
+    host_delay: Option<u64>,
+}
+
+#[derive(Deserialize, Getters, Default, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConvertConfig {
+    format: Option<String>,
+    quality: Option<u8>,
+    max_dimension: Option<u32>,
+}
+
+#[derive(Deserialize, Getters, Default, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RobotsConfig {
+    respect: bool,
+    agent: Option<String>,
+}
+
+#[derive(Deserialize, Getters, Default, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct DriverSection {
+    path: Option<String>,
+    port: Option<u16>,
+    url: Option<String>,
+}
+
+#[derive(Deserialize, Getters, Default, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BrowserSection {
+    kind: Option<String>,
+    headless: bool,
+    window_size: Option<String>,
+    user_agent: Option<String>,
+    proxy: Option<String>,
+    user_data_dir: Option<String>,
+    args: Vec<String>,
+}
+
+#[derive(Deserialize, Getters, Default, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ImageFilterConfig {
+    width: Option<usize>,
+    height: Option<usize>,
+    max_width: Option<usize>,
+    max_height: Option<usize>,
+    min_ratio: Option<String>,
+    max_ratio: Option<String>,
+    min_bytes: Option<usize>,
+    max_bytes: Option<usize>,
+    types: Vec<String>,
+    size_source: Option<String>,
+    allow_unknown_size: bool,
+    src_include: Option<String>,
+    src_exclude: Option<String>,
+    alt_include: Option<String>,
+    alt_exclude: Option<String>,
+    class_include: Option<String>,
+    class_exclude: Option<String>,
+    blacklist_dir: Option<String>,
+    skip_similar: bool,
+    phash: Option<String>,
+    phash_threshold: Option<u32>,
+}
+
+#[derive(Deserialize, Getters, Default, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct UrlFilterConfig {
+    tags: Vec<String>,
+    regex: Option<String>,
+}
+
+// Stage filters start from the job's filters, only the keys set here are replaced
+#[derive(Deserialize, Getters, Default, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct StageConfig {
+    name: Option<String>,
+    kind: String,
+    output: Option<String>,
+    depth: Option<usize>,
+    workers: Option<usize>,
+    image: ImageFilterConfig,
+    url: UrlFilterConfig,
+}
+
+impl ImageFilterConfig {
+    fn apply(&self, key: &str, filter: &mut ScrapeImageFilter) -> ScrapeResult<()> {
+        if !self.types.is_empty() {
+            filter.replace_mime_types(parse_all(&format!("{key}.types"), &self.types)?);
+        }
+
+        if let Some(width) = self.width {
+            filter.set_min_width(width);
+        }
+
+        if let Some(height) = self.height {
+            filter.set_min_height(height);
+        }
+
+        if let Some(size_source) = &self.size_source {
+            filter.set_size_source(parse(&format!("{key}.size-source"), size_source)?);
+        }
+
+        if self.allow_unknown_size {
+            filter.set_allow_unknown_size(true);
+        }
+
+        if let Some(width) = self.max_width {
+            filter.set_max_width(width);
+        }
+
+        if let Some(height) = self.max_height {
+            filter.set_max_height(height);
+        }
+
+        if let Some(ratio) = &self.min_ratio {
+            filter.set_min_aspect_ratio(parse(&format!("{key}.min-ratio"), ratio)?);
+        }
+
+        if let Some(ratio) = &self.max_ratio {
+            filter.set_max_aspect_ratio(parse(&format!("{key}.max-ratio"), ratio)?);
+        }
+
+        if let Some(bytes) = self.min_bytes {
+            filter.set_min_bytes(bytes);
+        }
+
+        if let Some(bytes) = self.max_bytes {
+            filter.set_max_bytes(bytes);
+        }
+
+        let src = patterns(
+            &format!("{key}.src"),
+            filter.src_filter(),
+            &self.src_include,
+            &self.src_exclude,
+        )?;
+        let alt = patterns(
+            &format!("{key}.alt"),
+            filter.alt_filter(),
+            &self.alt_include,
+            &self.alt_exclude,
+        )?;
+        let class = patterns(
+            &format!("{key}.class"),
+            filter.class_filter(),
+            &self.class_include,
+            &self.class_exclude,
+        )?;
+
+        filter.set_src_filter(src);
+        filter.set_alt_filter(alt);
+        filter.set_class_filter(class);
+
+        let mut similarity = filter.similarity().clone();
+
+        if let Some(algorithm) = &self.phash {
+            similarity.set_algorithm(parse(&format!("{key}.phash"), algorithm)?);
+        }
+
+        if let Some(threshold) = self.phash_threshold {
+            similarity.set_threshold(threshold);
+        }
+
+        if self.skip_similar {
+            similarity.set_skip_similar(true);
+        }
+
+        if let Some(dir) = &self.blacklist_dir {
+            similarity.set_blacklist(ImageBlacklist::load(dir)?);
+        }
+
+        filter.set_similarity(similarity);
+
+        Ok(())
+    }
+}
+
+impl UrlFilterConfig {
+    fn apply(&self, key: &str, filter: &mut ScrapeUrlFilter) -> ScrapeResult<()> {
+        if !self.tags.is_empty() {
+            filter.replace_tags(parse_all(&format!("{key}.tags"), &self.tags)?);
+        }
+
+        if let Some(regex) = &self.regex {
+            // The filter keeps its previous rule on an invalid one, report it instead
+            Regex::new(regex).map_err(|err| {
+                ScrapeError::ConfigError(format!("{key}.regex = \"{regex}\": {err}"))
+            })?;
+            filter.set_regex(regex.clone());
+        }
+
+        Ok(())
+    }
+}
+
+fn parse<T>(key: &str, value: &str) -> ScrapeResult<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|err| ScrapeError::ConfigError(format!("{key} = \"{value}\": {err}")))
+}
+
+// Include and exclude keys are `<key>-include` and `<key>-exclude`, unset ones keep the base
+fn patterns(
+    key: &str,
+    base: &PatternFilter,
+    include: &Option<String>,
+    exclude: &Option<String>,
+) -> ScrapeResult<PatternFilter> {
+    let mut patterns = base.clone();
+
+    if let Some(include) = include {
+        patterns.set_include(parse(&format!("{key}-include"), include)?);
+    }
+
+    if let Some(exclude) = exclude {
+        patterns.set_exclude(parse(&format!("{key}-exclude"), exclude)?);
+    }
+
+    Ok(patterns)
+}
+
+fn parse_all<T>(key: &str, values: &[String]) -> ScrapeResult<Vec<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    values.iter().map(|value| parse(key, value)).collect()
+}
+
+impl JobConfig {
+    // The format is picked from the extension, TOML unless it is .yaml or .yml
+    pub fn load(path: &str) -> ScrapeResult<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| ScrapeError::ConfigError(format!("Unable to read {path}: {err}")))?;
+
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&content)
+                .map_err(|err| ScrapeError::ConfigError(format!("{path}: {err}"))),
+            _ => toml::from_str(&content)
+                .map_err(|err| ScrapeError::ConfigError(format!("{path}: {err}"))),
+        }
+    }
+
+    pub fn strategy(&self) -> ScrapeResult<ScrapeStrategy> {
+        let mut strategy = ScrapeStrategy::default();
+
+        if let Some(workers) = self.workers {
+            strategy.set_number_of_windows(workers);
+        }
+
+        if let Some(output) = &self.output {
+            strategy.set_destination(output.clone());
+        }
+
+        if let Some(depth) = self.depth {
+            strategy.set_depth(depth);
+        }
+
+        strategy.set_resume(self.resume);
+
+        if let Some(engine) = &self.engine {
+            strategy.set_engine(parse("engine", engine)?);
+        }
+
+        let mut retry_policy = RetryPolicy::default();
+
+        match self.retry.attempts {
+            Some(0) => {
+                return Err(ScrapeError::ConfigError(String::from(
+                    "retry.attempts = 0: at least one attempt is required",
+                )))
+            }
+            Some(attempts) => {
+                retry_policy.set_max_attempts(attempts);
+            }
+            None => {}
+        }
+
+        if let Some(delay) = self.retry.delay {
+            retry_policy.set_base_delay(Duration::from_millis(delay));
+        }
+
+        if let Some(jitter) = self.retry.jitter {
+            retry_policy.set_jitter(Duration::from_millis(jitter));
+        }
+
+        if !self.retry.on.is_empty() {
+            retry_policy.replace_retry_on(parse_all("retry.on", &self.retry.on)?);
+        }
+
+        strategy.set_retry_policy(retry_policy);
+
+        let mut rate_limit = RateLimit::default();
+
+        match self.rate_limit.requests_per_second {
+            Some(requests) if !requests.is_finite() || requests <= 0.0 => {
+                return Err(ScrapeError::ConfigError(format!(
+                    "rate-limit.requests-per-second = {requests}: a positive number is required"
+                )))
+            }
+            Some(requests) => {
+                rate_limit.set_requests_per_second(requests);
+            }
+            None => {}
+        }
+
+        match self.rate_limit.host_windows {
+            Some(0) => {
+                return Err(ScrapeError::ConfigError(String::from(
+                    "rate-limit.host-windows = 0: at least one window is required",
+                )))
+            }
+            Some(windows) => {
+                rate_limit.set_max_windows_per_host(windows);
+            }
+            None => {}
+        }
+
+        if let Some(delay) = self.rate_limit.host_delay {
+            rate_limit.set_min_delay(Duration::from_millis(delay));
+        }
+
+        strategy.set_rate_limit(rate_limit);
+
+        let mut conversion = ImageConversion::default();
+
+        if let Some(format) = &self.convert.format {
+            conversion.set_format(parse("convert.format", format)?);
+        }
+
+        if let Some(quality) = self.convert.quality {
+            conversion.set_quality(quality);
+        }
+
+        if let Some(max_dimension) = self.convert.max_dimension {
+            conversion.set_max_dimension(max_dimension);
+        }
+
+        strategy.set_conversion(conversion);
+        strategy.set_respect_robots(self.robots.respect);
+
+        if let Some(agent) = &self.robots.agent {
+            strategy.set_robots_user_agent(agent.clone());
+        }
+
+        let mut driver = DriverConfig::default();
+
+        if let Some(path) = &self.driver.path {
+            driver.set_binary(path.clone());
+        }
+
+        if let Some(port) = self.driver.port {
+            driver.set_port(port);
+        }
+
+        if let Some(url) = &self.driver.url {
+            driver.set_url(url.clone());
+        }
+
+        strategy.set_driver(driver);
+
+        let mut browser = BrowserOptions::default();
+
+        if let Some(kind) = &self.browser.kind {
+            browser.set_kind(parse("browser.kind", kind)?);
+        }
+
+        browser.set_headless(self.browser.headless);
+
+        if let Some(window_size) = &self.browser.window_size {
+            browser.set_window_size(parse("browser.window-size", window_size)?);
+        }
+
+        if let Some(user_agent) = &self.browser.user_agent {
+            browser.set_user_agent(user_agent.clone());
+        }
+
+        if let Some(proxy) = &self.browser.proxy {
+            browser.set_proxy(proxy.clone());
+        }
+
+        if let Some(user_data_dir) = &self.browser.user_data_dir {
+            browser.set_user_data_dir(user_data_dir.clone());
+        }
+
+        for arg in &self.browser.args {
+            browser.add_arg(arg.clone());
+        }
+
+        strategy.set_browser_options(browser);
+
+        if let Some(url_format) = &self.url_format {
+            strategy.set_url_format(parse("url-format", url_format)?);
+        }
+
+        if let Some(image_name) = &self.image_name {
+            strategy.set_image_name(parse("image-name", image_name)?);
+        }
+
+        if let Some(pack) = &self.pack {
+            strategy.set_pack(parse("pack", pack)?);
+        }
+
+        strategy.set_comic_info(self.comic_info);
+
+        if !self.export.is_empty() {
+            strategy.replace_exports(parse_all("export", &self.export)?);
+        }
+
+        if let Some(dedup) = &self.dedup {
+            strategy.set_dedup(parse("dedup", dedup)?);
+        }
+
+        if let Some(dedup_index) = &self.dedup_index {
+            strategy.set_dedup_index(dedup_index.clone());
+        }
+
+        Ok(strategy)
+    }
+
+    pub fn image_filter(&self) -> ScrapeResult<ScrapeImageFilter> {
+        let mut filter = ScrapeImageFilter::default();
+
+        self.image.apply("image", &mut filter)?;
+
+        Ok(filter)
+    }
+
+    pub fn url_filter(&self) -> ScrapeResult<ScrapeUrlFilter> {
+        let mut filter = ScrapeUrlFilter::default();
+
+        self.url.apply("url", &mut filter)?;
+
+        Ok(filter)
+    }
+
+    // Builds the pipeline on top of the job's strategy and filters
+    pub fn pipeline(
+        &self,
+        strategy: &ScrapeStrategy,
+        url_filter: &ScrapeUrlFilter,
+        image_filter: &ScrapeImageFilter,
+    ) -> ScrapeResult<Vec<Stage>> {
+        let mut stages = vec![];
+
+        for (index, config) in self.stages.iter().enumerate() {
+            let key = format!("stages[{index}]");
+            let kind: StageKind = parse(&format!("{key}.kind"), &config.kind)?;
+            let mut stage_strategy = strategy.clone();
+
+            match (&config.output, kind) {
+                (Some(output), _) => {
+                    stage_strategy.set_destination(output.clone());
+                }
+                // Intermediate urls are passed in memory unless an output is given
+                (None, StageKind::Urls) => {
+                    stage_strategy.set_destination(String::new());
+                }
+                (None, StageKind::Images) => {}
+            }
+
+            if let Some(depth) = config.depth {
+                stage_strategy.set_depth(depth);
+            }
+
+            if let Some(workers) = config.workers {
+                stage_strategy.set_number_of_windows(workers);
+            }
+
+            let mut stage_url_filter = url_filter.clone();
+            let mut stage_image_filter = image_filter.clone();
+
+            config
+                .url
+                .apply(&format!("{key}.url"), &mut stage_url_filter)?;
+            config
+                .image
+                .apply(&format!("{key}.image"), &mut stage_image_filter)?;
+
+            let mut stage = Stage::new(kind, stage_strategy);
+
+            stage
+                .set_name(
+                    config
+                        .name
+                        .clone()
+                        .unwrap_or(format!("{}-{}", kind, index + 1)),
+                )
+                .set_url_filter(stage_url_filter)
+                .set_image_filter(stage_image_filter);
+            stages.push(stage);
+        }
+
+        Ok(stages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    const TOML_JOB: &str = r#"
+paths = ["url-output"]
+image-download = true
+workers = 3
+output = "image-output"
+
+[retry]
+attempts = 5
+delay = 2000
+on = ["timeout", "server-error"]
+
+[rate-limit]
+requests-per-second = 2.0
+host-windows = 2
+
+[robots]
+respect = true
+agent = "scraper"
+
+[image]
+width = 300
+types = ["image/jpeg"]
+
+[[stages]]
+kind = "urls"
+[stages.url]
+regex = "/chap-"
+"#;
+
+    const YAML_JOB: &str = r#"
+paths: [url-output]
+image-download: true
+workers: 3
+output: image-output
+retry:
+  attempts: 5
+  delay: 2000
+  on: [timeout, server-error]
+rate-limit:
+  requests-per-second: 2.0
+  host-windows: 2
+robots:
+  respect: true
+  agent: scraper
+image:
+  width: 300
+  types: [image/jpeg]
+stages:
+  - kind: urls
+    url:
+      regex: /chap-
+"#;
+
+    fn load(name: &str, content: &str) -> ScrapeResult<JobConfig> {
+        let path = env::temp_dir().join(format!("scraper-job-{}-{name}", std::process::id()));
+
+        fs::write(&path, content).unwrap();
+
+        let config = JobConfig::load(&path.to_string_lossy());
+
+        fs::remove_file(&path).unwrap();
+
+        config
+    }
+
+    #[test]
+    fn toml_and_yaml_jobs_give_the_same_config() {
+        let toml = load("job.toml", TOML_JOB).unwrap();
+        let yaml = load("job.yaml", YAML_JOB).unwrap();
+
+        assert_eq!(format!("{toml:?}"), format!("{yaml:?}"));
+        assert_eq!(toml.paths(), &vec![String::from("url-output")]);
+        assert!(toml.image_download());
+        assert_eq!(toml.workers(), &Some(3));
+        assert_eq!(toml.retry().attempts(), &Some(5));
+        assert_eq!(toml.retry().on(), &vec!["timeout", "server-error"]);
+        assert_eq!(toml.rate_limit().requests_per_second(), &Some(2.0));
+        assert!(toml.robots().respect());
+        assert_eq!(toml.image().width(), &Some(300));
+        assert_eq!(toml.stages()[0].kind(), "urls");
+        assert_eq!(toml.stages()[0].url().regex().as_deref(), Some("/chap-"));
+    }
+
+    #[test]
+    fn job_settings_reach_the_strategy() {
+        let strategy = load("strategy.toml", TOML_JOB).unwrap().strategy().unwrap();
+
+        assert_eq!(strategy.number_of_windows(), &3);
+        assert_eq!(strategy.retry_policy().max_attempts(), &5);
+        assert_eq!(
+            strategy.retry_policy().base_delay(),
+            &Duration::from_millis(2000)
+        );
+        assert_eq!(strategy.rate_limit().max_windows_per_host(), &Some(2));
+    }
+
+    #[test]
+    fn rate_limits_turning_the_limit_off_are_rejected() {
+        for job in [
+            "[rate-limit]\nhost-windows = 0\n",
+            "[rate-limit]\nrequests-per-second = 0.0\n",
+            "[rate-limit]\nrequests-per-second = -1.0\n",
+        ] {
+            assert!(matches!(
+                load("rate.toml", job).unwrap().strategy(),
+                Err(ScrapeError::ConfigError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn unknown_keys_and_zero_attempts_are_rejected() {
+        assert!(matches!(
+            load("unknown.toml", "retry-delay = 2000\n"),
+            Err(ScrapeError::ConfigError(_))
+        ));
+        assert!(matches!(
+            load("zero.yml", "retry:\n  attempts: 0\n")
+                .unwrap()
+                .strategy(),
+            Err(ScrapeError::ConfigError(_))
+        ));
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use thirtyfour::prelude::WebDriverError;
 
 pub type ScrapeResult<T> = Result<T, ScrapeError>;
@@ -5,6 +7,46 @@ pub type ScrapeResult<T> = Result<T, ScrapeError>;
 #[derive(Debug)]
 pub enum ScrapeError {
     WebDriverError(WebDriverError),
+    HttpError(reqwest::Error),
     IncompatibleError(String),
     CmdError(String),
+    IoError(std::io::Error),
+    // Page could not be loaded
+    NavigationError(String),
+    // Resource referenced by a page could not be downloaded
+    FetchError(String),
+    TimeoutError(String),
+    // Server answered with an unsuccessful status code
+    StatusError(u16, String),
+    // Anti-bot page (e.g. Cloudflare) served instead of the content
+    ChallengeError(String),
+    // Downloaded content is malformed or not in the expected format
+    DecodeError(String),
+    // Job configuration is missing, malformed or inconsistent
+    ConfigError(String),
+    // Receiver stopped, nothing scraped can be saved anymore
+    ChannelClosed,
+    // Run stopped by Ctrl-C or SIGTERM
+    Interrupted,
+}
+
+impl Display for ScrapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScrapeError::WebDriverError(err) => write!(f, "WebDriver error: {err}"),
+            ScrapeError::HttpError(err) => write!(f, "HTTP error: {err}"),
+            ScrapeError::IncompatibleError(message) => write!(f, "Incompatible: {message}"),
+            ScrapeError::CmdError(message) => write!(f, "Command error: {message}"),
+            ScrapeError::IoError(err) => write!(f, "IO error: {err}"),
+            ScrapeError::NavigationError(message) => write!(f, "Navigation failed: {message}"),
+            ScrapeError::FetchError(message) => write!(f, "Fetch failed: {message}"),
+            ScrapeError::TimeoutError(message) => write!(f, "Timed out: {message}"),
+            ScrapeError::StatusError(status, url) => write!(f, "Status {status} from {url}"),
+            ScrapeError::ChallengeError(url) => write!(f, "Challenge page served for {url}"),
+            ScrapeError::DecodeError(message) => write!(f, "Decode failed: {message}"),
+            ScrapeError::ConfigError(message) => write!(f, "Invalid config: {message}"),
+            ScrapeError::ChannelClosed => write!(f, "Channel closed"),
+            ScrapeError::Interrupted => write!(f, "Interrupted"),
+        }
+    }
 }
@@ -0,0 +1,386 @@
+mod cbz;
+mod epub;
+mod pdf;
+
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use derive_getters::Getters;
+use image::ImageReader;
+
+use crate::{
+    error::{ScrapeError, ScrapeResult},
+    scraper::ImageMimeType,
+};
+
+pub use self::cbz::*;
+pub use self::epub::*;
+pub use self::pdf::*;
+
+// How the images of a finished page are written
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum PackMode {
+    #[default]
+    Files,
+    Cbz,
+    // Loose files and a CBZ archive
+    Both,
+}
+
+impl Display for PackMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackMode::Files => write!(f, "files"),
+            PackMode::Cbz => write!(f, "cbz"),
+            PackMode::Both => write!(f, "both"),
+        }
+    }
+}
+
+impl FromStr for PackMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "files" => Ok(Self::Files),
+            "cbz" => Ok(Self::Cbz),
+            "both" => Ok(Self::Both),
+            _ => Err("Unsupported pack mode"),
+        }
+    }
+}
+
+impl PackMode {
+    pub fn has_files(&self) -> bool {
+        matches!(self, PackMode::Files | PackMode::Both)
+    }
+
+    pub fn has_archive(&self) -> bool {
+        matches!(self, PackMode::Cbz | PackMode::Both)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExportFormat {
+    Cbz,
+    Epub,
+    Pdf,
+}
+
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::Cbz => write!(f, "cbz"),
+            ExportFormat::Epub => write!(f, "epub"),
+            ExportFormat::Pdf => write!(f, "pdf"),
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cbz" => Ok(Self::Cbz),
+            "epub" => Ok(Self::Epub),
+            "pdf" => Ok(Self::Pdf),
+            _ => Err("Unsupported export format"),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn exporter(&self, comic_info: bool) -> Box<dyn Exporter> {
+        match self {
+            ExportFormat::Cbz => Box::new(CbzExporter::new(comic_info)),
+            ExportFormat::Epub => Box::new(EpubExporter),
+            ExportFormat::Pdf => Box::new(PdfExporter),
+        }
+    }
+}
+
+#[derive(Getters)]
+pub struct BookPage {
+    extension: String,
+    content: Vec<u8>,
+}
+
+impl BookPage {
+    pub fn new(extension: String, content: Vec<u8>) -> Self {
+        Self { extension, content }
+    }
+
+    pub fn media_type(&self) -> &'static str {
+        match ImageMimeType::from_extension(&self.extension) {
+            Some(mime_type) => mime_type.as_str(),
+            None => "application/octet-stream",
+        }
+    }
+
+    // Read from the image header, the content is not decoded
+    pub fn dimensions(&self) -> ScrapeResult<(u32, u32)> {
+        ImageReader::new(Cursor::new(&self.content))
+            .with_guessed_format()
+            .map_err(ScrapeError::IoError)?
+            .into_dimensions()
+            .map_err(|err| ScrapeError::DecodeError(err.to_string()))
+    }
+}
+
+// Images of one title in reading order
+#[derive(Getters)]
+pub struct Book {
+    title: String,
+    url: Option<String>,
+    pages: Vec<BookPage>,
+}
+
+impl Book {
+    pub fn new(title: String, url: Option<String>, pages: Vec<BookPage>) -> Self {
+        Self { title, url, pages }
+    }
+}
+
+pub trait Exporter {
+    fn extension(&self) -> &'static str;
+
+    fn export(&self, book: &Book, path: &Path) -> ScrapeResult<()>;
+}
+
+fn zip_error(err: zip::result::ZipError) -> ScrapeError {
+    ScrapeError::IoError(std::io::Error::other(err))
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum NameChunk {
+    Number(u64),
+    Text(String),
+}
+
+// Sorts `2.png` before `10.png`
+fn natural_key(name: &str) -> Vec<NameChunk> {
+    let mut chunks = vec![];
+    let mut current = String::new();
+
+    for c in name.chars() {
+        if !current.is_empty()
+            && c.is_ascii_digit() != current.starts_with(|c: char| c.is_ascii_digit())
+        {
+            chunks.push(current);
+            current = String::new();
+        }
+
+        current.push(c);
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+        .into_iter()
+        .map(|chunk| match chunk.parse() {
+            Ok(number) => NameChunk::Number(number),
+            Err(_) => NameChunk::Text(chunk.to_lowercase()),
+        })
+        .collect()
+}
+
+fn compare_names(a: &Path, b: &Path) -> Ordering {
+    let name = |path: &Path| {
+        path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string()
+    };
+
+    natural_key(&name(a)).cmp(&natural_key(&name(b)))
+}
+
+fn is_image(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    path.is_file() && BookPage::new(extension, vec![]).media_type() != "application/octet-stream"
+}
+
+// Book made of the images directly inside the directory, in natural order of their names
+pub fn read_book(dir: &Path) -> ScrapeResult<Option<Book>> {
+    let mut files = fs::read_dir(dir)
+        .map_err(ScrapeError::IoError)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_image(path))
+        .collect::<Vec<_>>();
+
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    files.sort_by(|a, b| compare_names(a, b));
+
+    let mut pages = vec![];
+
+    for file in files {
+        let extension = file
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        pages.push(BookPage::new(
+            extension,
+            fs::read(&file).map_err(ScrapeError::IoError)?,
+        ));
+    }
+
+    let title = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("images"));
+
+    Ok(Some(Book::new(title, None, pages)))
+}
+
+// Every directory under the root directly containing images, hidden ones are skipped
+pub fn find_book_dirs(root: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(_) => return dirs,
+    };
+    let mut has_images = false;
+
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+
+        if path.is_dir() {
+            dirs.extend(find_book_dirs(&path));
+        } else if is_image(&path) {
+            has_images = true;
+        }
+    }
+
+    if has_images {
+        dirs.insert(0, root.to_path_buf());
+    }
+
+    dirs
+}
+
+// `<dir>.<ext>` next to the directory, or inside it for the root of the output
+pub fn export_path(root: &Path, dir: &Path, extension: &str) -> PathBuf {
+    match dir == root {
+        true => dir.join(format!(
+            "{}.{extension}",
+            dir.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| String::from("images"))
+        )),
+        false => {
+            let mut path = dir.as_os_str().to_owned();
+
+            path.push(format!(".{extension}"));
+
+            PathBuf::from(path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageFormat, Rgb, RgbImage};
+    use zip::{CompressionMethod, ZipArchive};
+
+    use super::*;
+
+    pub(super) fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut content = Cursor::new(vec![]);
+
+        RgbImage::from_fn(width, height, |x, y| Rgb([x as u8, y as u8, 0]))
+            .write_to(&mut content, ImageFormat::Png)
+            .unwrap();
+
+        content.into_inner()
+    }
+
+    // Two pages of different sizes
+    pub(super) fn book() -> Book {
+        Book::new(
+            String::from("Chapter <1>"),
+            Some(String::from("https://example.com/1")),
+            vec![
+                BookPage::new(String::from("png"), png(40, 60)),
+                BookPage::new(String::from("png"), png(30, 20)),
+            ],
+        )
+    }
+
+    pub(super) fn entries(content: Vec<u8>) -> Vec<(String, CompressionMethod)> {
+        let mut archive = ZipArchive::new(Cursor::new(content)).unwrap();
+
+        (0..archive.len())
+            .map(|index| {
+                let entry = archive.by_index(index).unwrap();
+
+                (entry.name().unwrap().to_string(), entry.compression())
+            })
+            .collect()
+    }
+
+    // Pages no exporter can lay out
+    pub(super) fn unusable_pages() -> Vec<BookPage> {
+        vec![
+            BookPage::new(
+                String::from("svg"),
+                b"<svg width=\"10\" height=\"10\"/>".to_vec(),
+            ),
+            BookPage::new(String::from("png"), b"<html>Not found</html>".to_vec()),
+        ]
+    }
+
+    pub(super) fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("scraper-export-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn natural_key_sorts_numbers_by_value() {
+        let mut names = vec!["10.png", "2.png", "1.png", "cover.png", "2a.png", "02.jpg"];
+
+        names.sort_by_key(|name| natural_key(name));
+
+        assert_eq!(
+            names,
+            vec!["1.png", "02.jpg", "2.png", "2a.png", "10.png", "cover.png"]
+        );
+    }
+
+    #[test]
+    fn natural_key_compares_text_around_numbers() {
+        assert!(natural_key("page 9") < natural_key("page 10"));
+        assert!(natural_key("chapter 2 page 30") < natural_key("chapter 10 page 1"));
+        assert!(natural_key("a") < natural_key("b"));
+    }
+}
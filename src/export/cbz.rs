@@ -0,0 +1,135 @@
+use std::{
+    fs,
+    io::{Cursor, Write},
+    path::Path,
+};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, DateTime, ZipWriter};
+
+use crate::error::{ScrapeError, ScrapeResult};
+
+use super::{escape_xml, zip_error, Book, Exporter};
+
+// Comic book archive: a zip of the images, named so that readers sort them in order
+#[derive(Default)]
+pub struct CbzExporter {
+    comic_info: bool,
+}
+
+impl CbzExporter {
+    pub fn new(comic_info: bool) -> Self {
+        Self { comic_info }
+    }
+
+    // Metadata read by comic readers, see https://anansi-project.github.io/docs/comicinfo
+    fn comic_info(&self, book: &Book) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<ComicInfo xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\">\n",
+        );
+
+        xml.push_str(&format!("  <Title>{}</Title>\n", escape_xml(book.title())));
+
+        if let Some(url) = book.url() {
+            xml.push_str(&format!("  <Web>{}</Web>\n", escape_xml(url)));
+        }
+
+        xml.push_str(&format!(
+            "  <PageCount>{}</PageCount>\n",
+            book.pages().len()
+        ));
+        xml.push_str("</ComicInfo>\n");
+
+        xml
+    }
+
+    // Same book, same bytes: entries carry a fixed date so that reruns can recognize their archives
+    pub fn archive(&self, book: &Book) -> ScrapeResult<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        // Images are already compressed
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .last_modified_time(DateTime::default());
+        let width = book.pages().len().to_string().len().max(3);
+
+        for (index, page) in book.pages().iter().enumerate() {
+            zip.start_file(
+                format!("{:0width$}.{}", index + 1, page.extension()),
+                options,
+            )
+            .map_err(zip_error)?;
+            zip.write_all(page.content())
+                .map_err(ScrapeError::IoError)?;
+        }
+
+        if self.comic_info {
+            zip.start_file("ComicInfo.xml", options)
+                .map_err(zip_error)?;
+            zip.write_all(self.comic_info(book).as_bytes())
+                .map_err(ScrapeError::IoError)?;
+        }
+
+        Ok(zip.finish().map_err(zip_error)?.into_inner())
+    }
+}
+
+impl Exporter for CbzExporter {
+    fn extension(&self) -> &'static str {
+        "cbz"
+    }
+
+    fn export(&self, book: &Book, path: &Path) -> ScrapeResult<()> {
+        fs::write(path, self.archive(book)?).map_err(ScrapeError::IoError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::export::tests::{book, entries, png};
+
+    #[test]
+    fn archive_lists_pages_in_order_then_metadata() {
+        let content = CbzExporter::new(true).archive(&book()).unwrap();
+        let names = entries(content.clone())
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        let mut archive = ZipArchive::new(Cursor::new(content)).unwrap();
+
+        assert_eq!(names, vec!["001.png", "002.png", "ComicInfo.xml"]);
+
+        let mut page = vec![];
+
+        archive
+            .by_name("002.png")
+            .unwrap()
+            .read_to_end(&mut page)
+            .unwrap();
+
+        assert_eq!(page, png(30, 20));
+
+        let mut comic_info = String::new();
+
+        archive
+            .by_name("ComicInfo.xml")
+            .unwrap()
+            .read_to_string(&mut comic_info)
+            .unwrap();
+
+        assert!(comic_info.contains("<Title>Chapter &lt;1&gt;</Title>"));
+        assert!(comic_info.contains("<PageCount>2</PageCount>"));
+    }
+
+    #[test]
+    fn archive_of_the_same_book_has_the_same_bytes() {
+        let exporter = CbzExporter::new(false);
+        let content = exporter.archive(&book()).unwrap();
+
+        assert_eq!(content, exporter.archive(&book()).unwrap());
+        assert_eq!(entries(content).len(), 2);
+    }
+}
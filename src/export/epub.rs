@@ -0,0 +1,287 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Utc};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    error::{ScrapeError, ScrapeResult},
+    scraper::content_hash,
+};
+
+use super::{escape_xml, zip_error, Book, Exporter};
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+// EPUB 3 fixed-layout book, one image per page sized to the image
+pub struct EpubExporter;
+
+struct EpubPage {
+    image: String,
+    document: String,
+    media_type: &'static str,
+    width: u32,
+    height: u32,
+}
+
+impl EpubExporter {
+    fn package(&self, book: &Book, pages: &[EpubPage]) -> String {
+        let now: DateTime<Utc> = SystemTime::now().into();
+        let mut manifest = String::from(
+            "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+        );
+        let mut spine = String::new();
+
+        for (index, page) in pages.iter().enumerate() {
+            let cover = if index == 0 {
+                " properties=\"cover-image\""
+            } else {
+                ""
+            };
+
+            manifest.push_str(&format!(
+                "    <item id=\"image-{index}\" href=\"{}\" media-type=\"{}\"{cover}/>\n",
+                page.image, page.media_type
+            ));
+            manifest.push_str(&format!(
+                "    <item id=\"page-{index}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+                page.document
+            ));
+            spine.push_str(&format!("    <itemref idref=\"page-{index}\"/>\n"));
+        }
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" prefix="rendition: http://www.idpf.org/vocab/rendition/#">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">urn:sha256:{}</dc:identifier>
+    <dc:title>{}</dc:title>
+    <dc:language>en</dc:language>{}
+    <meta property="dcterms:modified">{}</meta>
+    <meta property="rendition:layout">pre-paginated</meta>
+    <meta property="rendition:spread">none</meta>
+  </metadata>
+  <manifest>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#,
+            content_hash(book.title().as_bytes()),
+            escape_xml(book.title()),
+            match book.url() {
+                Some(url) => format!("\n    <dc:source>{}</dc:source>", escape_xml(url)),
+                None => String::new(),
+            },
+            now.format("%Y-%m-%dT%H:%M:%SZ"),
+        )
+    }
+
+    fn navigation(&self, book: &Book, pages: &[EpubPage]) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>{title}</title></head>
+<body>
+  <nav epub:type="toc"><ol><li><a href="{}">{title}</a></li></ol></nav>
+</body>
+</html>
+"#,
+            pages
+                .first()
+                .map(|page| page.document.as_str())
+                .unwrap_or_default(),
+            title = escape_xml(book.title()),
+        )
+    }
+
+    fn document(&self, book: &Book, page: &EpubPage) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head>
+  <title>{}</title>
+  <meta name="viewport" content="width={width}, height={height}"/>
+  <style>html, body {{ margin: 0; padding: 0; }} img {{ display: block; }}</style>
+</head>
+<body><img src="{}" width="{width}" height="{height}" alt=""/></body>
+</html>
+"#,
+            escape_xml(book.title()),
+            page.image.replace("images/", "../images/"),
+            width = page.width,
+            height = page.height,
+        )
+    }
+}
+
+impl Exporter for EpubExporter {
+    fn extension(&self) -> &'static str {
+        "epub"
+    }
+
+    fn export(&self, book: &Book, path: &Path) -> ScrapeResult<()> {
+        let width = book.pages().len().to_string().len().max(3);
+        let mut pages = vec![];
+        let mut contents = vec![];
+
+        for page in book.pages() {
+            // Fixed-layout pages need the image size, e.g. SVG images cannot be laid out
+            let (image_width, image_height) = match page.dimensions() {
+                Ok(dimensions) => dimensions,
+                Err(err) => {
+                    eprintln!("Skipping a page of {}: {err}", book.title());
+                    continue;
+                }
+            };
+            let number = pages.len() + 1;
+
+            pages.push(EpubPage {
+                image: format!("images/{:0width$}.{}", number, page.extension()),
+                document: format!("pages/{:0width$}.xhtml", number),
+                media_type: page.media_type(),
+                width: image_width,
+                height: image_height,
+            });
+            contents.push(page.content());
+        }
+
+        if pages.is_empty() {
+            return Err(ScrapeError::DecodeError(format!(
+                "No page of {} can be laid out",
+                book.title()
+            )));
+        }
+
+        let file = File::create(path).map_err(ScrapeError::IoError)?;
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        // The mimetype entry must come first and stay uncompressed
+        let mut entries: Vec<(String, Vec<u8>)> = vec![
+            (String::from("mimetype"), b"application/epub+zip".to_vec()),
+            (
+                String::from("META-INF/container.xml"),
+                CONTAINER.as_bytes().to_vec(),
+            ),
+            (
+                String::from("OEBPS/content.opf"),
+                self.package(book, &pages).into_bytes(),
+            ),
+            (
+                String::from("OEBPS/nav.xhtml"),
+                self.navigation(book, &pages).into_bytes(),
+            ),
+        ];
+
+        for page in &pages {
+            entries.push((
+                format!("OEBPS/{}", page.document),
+                self.document(book, page).into_bytes(),
+            ));
+        }
+
+        for (name, content) in entries {
+            zip.start_file(name, options).map_err(zip_error)?;
+            zip.write_all(&content).map_err(ScrapeError::IoError)?;
+        }
+
+        for (content, page) in contents.into_iter().zip(&pages) {
+            zip.start_file(format!("OEBPS/{}", page.image), options)
+                .map_err(zip_error)?;
+            zip.write_all(content).map_err(ScrapeError::IoError)?;
+        }
+
+        zip.finish()
+            .map_err(zip_error)?
+            .flush()
+            .map_err(ScrapeError::IoError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::export::{
+        tests::{book, entries, png, temp_path, unusable_pages},
+        BookPage,
+    };
+
+    #[test]
+    fn export_starts_with_the_stored_mimetype() {
+        let path = temp_path("book.epub");
+
+        EpubExporter.export(&book(), &path).unwrap();
+
+        let content = fs::read(&path).unwrap();
+        let entries = entries(content.clone());
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            entries[0],
+            (String::from("mimetype"), CompressionMethod::Stored)
+        );
+        assert_eq!(&content[30..38], b"mimetype");
+        assert_eq!(&content[38..58], b"application/epub+zip");
+
+        let names = entries
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            names[1..],
+            [
+                "META-INF/container.xml",
+                "OEBPS/content.opf",
+                "OEBPS/nav.xhtml",
+                "OEBPS/pages/001.xhtml",
+                "OEBPS/pages/002.xhtml",
+                "OEBPS/images/001.png",
+                "OEBPS/images/002.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn export_skips_pages_without_size() {
+        let path = temp_path("skipped.epub");
+        let mut pages = unusable_pages();
+
+        pages.push(BookPage::new(String::from("png"), png(30, 20)));
+        EpubExporter
+            .export(&Book::new(String::from("Skipped"), None, pages), &path)
+            .unwrap();
+
+        let names = entries(fs::read(&path).unwrap())
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+
+        fs::remove_file(&path).unwrap();
+
+        assert!(names.contains(&String::from("OEBPS/images/001.png")));
+        assert!(!names.contains(&String::from("OEBPS/pages/002.xhtml")));
+    }
+
+    #[test]
+    fn export_fails_without_any_page() {
+        let path = temp_path("empty.epub");
+        let book = Book::new(String::from("Empty"), None, unusable_pages());
+
+        assert!(EpubExporter.export(&book, &path).is_err());
+        assert!(!path.exists());
+    }
+}
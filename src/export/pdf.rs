@@ -0,0 +1,337 @@
+use std::{fs, io::Write, path::Path};
+
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::error::{ScrapeError, ScrapeResult};
+
+use super::{Book, BookPage, Exporter};
+
+// Pixels are laid out at 96 dpi
+const POINTS_PER_PIXEL: f64 = 0.75;
+// Largest page side most viewers accept
+const MAX_PAGE_POINTS: f64 = 14400.0;
+
+struct PdfImage {
+    width: u32,
+    height: u32,
+    color_space: &'static str,
+    filter: &'static str,
+    // Adobe CMYK JPEGs are stored inverted
+    decode: Option<&'static str>,
+    data: Vec<u8>,
+}
+
+// Size and number of components read from the first SOF segment of a JPEG
+fn jpeg_info(content: &[u8]) -> Option<(u32, u32, u8)> {
+    let mut i = 2;
+
+    while i + 9 < content.len() {
+        if content[i] != 0xFF {
+            return None;
+        }
+
+        let marker = content[i + 1];
+        let length = u16::from_be_bytes([content[i + 2], content[i + 3]]) as usize;
+
+        if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+            let height = u16::from_be_bytes([content[i + 5], content[i + 6]]) as u32;
+            let width = u16::from_be_bytes([content[i + 7], content[i + 8]]) as u32;
+
+            return Some((width, height, content[i + 9]));
+        }
+
+        i += 2 + length;
+    }
+
+    None
+}
+
+// JPEGs are embedded as is, other formats are decoded and deflated
+fn encode_image(page: &BookPage) -> ScrapeResult<PdfImage> {
+    if page.media_type() == "image/jpeg" {
+        if let Some((width, height, components)) = jpeg_info(page.content()) {
+            let (color_space, decode) = match components {
+                1 => ("/DeviceGray", None),
+                3 => ("/DeviceRGB", None),
+                _ => ("/DeviceCMYK", Some("[1 0 1 0 1 0 1 0]")),
+            };
+
+            return Ok(PdfImage {
+                width,
+                height,
+                color_space,
+                filter: "/DCTDecode",
+                decode,
+                data: page.content().clone(),
+            });
+        }
+    }
+
+    let decoded = image::load_from_memory(page.content())
+        .map_err(|err| ScrapeError::DecodeError(err.to_string()))?
+        .to_rgba8();
+    // Transparent pixels are drawn over a white page
+    let pixels = decoded
+        .pixels()
+        .flat_map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+
+            [blend(r), blend(g), blend(b)]
+        })
+        .collect::<Vec<_>>();
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+
+    encoder.write_all(&pixels).map_err(ScrapeError::IoError)?;
+
+    Ok(PdfImage {
+        width: decoded.width(),
+        height: decoded.height(),
+        color_space: "/DeviceRGB",
+        filter: "/FlateDecode",
+        decode: None,
+        data: encoder.finish().map_err(ScrapeError::IoError)?,
+    })
+}
+
+// PDF text string, UTF-16BE so that any title can be stored
+fn text_string(value: &str) -> String {
+    let hex = value
+        .encode_utf16()
+        .map(|unit| format!("{unit:04X}"))
+        .collect::<String>();
+
+    format!("<FEFF{hex}>")
+}
+
+#[derive(Default)]
+struct PdfWriter {
+    out: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl PdfWriter {
+    fn object(&mut self, id: usize, body: &[u8]) {
+        if self.offsets.len() <= id {
+            self.offsets.resize(id + 1, 0);
+        }
+
+        self.offsets[id] = self.out.len();
+        self.out
+            .extend_from_slice(format!("{id} 0 obj\n").as_bytes());
+        self.out.extend_from_slice(body);
+        self.out.extend_from_slice(b"\nendobj\n");
+    }
+
+    fn stream(&mut self, id: usize, dictionary: &str, data: &[u8]) {
+        let mut body = format!("<< {dictionary} /Length {} >>\nstream\n", data.len()).into_bytes();
+
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\nendstream");
+        self.object(id, &body);
+    }
+
+    fn finish(mut self, root: usize, info: usize) -> Vec<u8> {
+        let xref = self.out.len();
+
+        self.out.extend_from_slice(
+            format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len()).as_bytes(),
+        );
+
+        for offset in self.offsets.iter().skip(1) {
+            self.out
+                .extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+        }
+
+        self.out.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root {root} 0 R /Info {info} 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+                self.offsets.len()
+            )
+            .as_bytes(),
+        );
+
+        self.out
+    }
+}
+
+// One image per page, each page has the size of its image
+pub struct PdfExporter;
+
+impl Exporter for PdfExporter {
+    fn extension(&self) -> &'static str {
+        "pdf"
+    }
+
+    fn export(&self, book: &Book, path: &Path) -> ScrapeResult<()> {
+        let mut writer = PdfWriter {
+            out: b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec(),
+            ..Default::default()
+        };
+        // Images the decoder does not support, e.g. SVG, are left out
+        let images = book
+            .pages()
+            .iter()
+            .filter_map(|page| match encode_image(page) {
+                Ok(image) => Some(image),
+                Err(err) => {
+                    eprintln!("Skipping a page of {}: {err}", book.title());
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if images.is_empty() {
+            return Err(ScrapeError::DecodeError(format!(
+                "No page of {} can be decoded",
+                book.title()
+            )));
+        }
+
+        // Objects 1 to 3 are the catalog, the page tree and the document info,
+        // then every page takes three objects: the page, its content and its image
+        let page_id = |index: usize| 4 + index * 3;
+        let kids = (0..images.len())
+            .map(|index| format!("{} 0 R", page_id(index)))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        writer.object(1, b"<< /Type /Catalog /Pages 2 0 R >>");
+        writer.object(
+            2,
+            format!("<< /Type /Pages /Kids [{kids}] /Count {} >>", images.len()).as_bytes(),
+        );
+        writer.object(
+            3,
+            format!(
+                "<< /Title {} /Producer (scraper) >>",
+                text_string(book.title())
+            )
+            .as_bytes(),
+        );
+
+        for (index, image) in images.iter().enumerate() {
+            let id = page_id(index);
+            let scale =
+                POINTS_PER_PIXEL.min(MAX_PAGE_POINTS / image.width.max(image.height).max(1) as f64);
+            let width = image.width as f64 * scale;
+            let height = image.height as f64 * scale;
+
+            writer.object(
+                id,
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {width:.2} {height:.2}] /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+                    id + 2,
+                    id + 1
+                )
+                .as_bytes(),
+            );
+            writer.stream(
+                id + 1,
+                "",
+                format!("q {width:.2} 0 0 {height:.2} 0 0 cm /Im0 Do Q").as_bytes(),
+            );
+            writer.stream(
+                id + 2,
+                &format!(
+                    "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} /BitsPerComponent 8 /Filter {}{}",
+                    image.width,
+                    image.height,
+                    image.color_space,
+                    image.filter,
+                    image
+                        .decode
+                        .map(|decode| format!(" /Decode {decode}"))
+                        .unwrap_or_default()
+                ),
+                &image.data,
+            );
+        }
+
+        fs::write(path, writer.finish(1, 3)).map_err(ScrapeError::IoError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::tests::{book, png, temp_path, unusable_pages};
+
+    fn export(book: &Book, name: &str) -> ScrapeResult<Vec<u8>> {
+        let path = temp_path(name);
+        let result = PdfExporter.export(book, &path);
+        let content = fs::read(&path);
+
+        let _ = fs::remove_file(&path);
+
+        result.map(|_| content.unwrap())
+    }
+
+    // Offsets listed by the cross-reference table, the free entry 0 excluded
+    fn xref_offsets(content: &[u8]) -> Vec<usize> {
+        let position = content
+            .windows(10)
+            .rposition(|window| window == b"startxref\n")
+            .unwrap();
+        let start = String::from_utf8_lossy(&content[position + 10..])
+            .lines()
+            .next()
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+        // The table and the trailer are plain ASCII
+        let table = String::from_utf8(content[start..].to_vec()).unwrap();
+
+        assert!(table.starts_with("xref\n"));
+
+        table
+            .lines()
+            .skip(3)
+            .take_while(|line| line.ends_with(" n "))
+            .map(|line| line[..10].parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn xref_offsets_point_at_their_objects() {
+        let content = export(&book(), "book.pdf").unwrap();
+        let offsets = xref_offsets(&content);
+
+        assert!(content.starts_with(b"%PDF-1.4\n"));
+        // Catalog, page tree, info and three objects per page
+        assert_eq!(offsets.len(), 3 + 2 * 3);
+
+        for (index, offset) in offsets.into_iter().enumerate() {
+            assert!(content[offset..].starts_with(format!("{} 0 obj\n", index + 1).as_bytes()));
+        }
+
+        let text = String::from_utf8_lossy(&content);
+
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("/MediaBox [0 0 30.00 45.00]"));
+        assert!(text.contains("/Width 30 /Height 20"));
+    }
+
+    #[test]
+    fn export_skips_pages_the_decoder_does_not_support() {
+        let mut pages = unusable_pages();
+
+        pages.push(BookPage::new(String::from("png"), png(30, 20)));
+
+        let content = export(
+            &Book::new(String::from("Skipped"), None, pages),
+            "skipped.pdf",
+        )
+        .unwrap();
+
+        assert_eq!(xref_offsets(&content).len(), 3 + 3);
+    }
+
+    #[test]
+    fn export_fails_without_any_page() {
+        let book = Book::new(String::from("Empty"), None, unusable_pages());
+
+        assert!(export(&book, "empty.pdf").is_err());
+        assert!(!temp_path("empty.pdf").exists());
+    }
+}
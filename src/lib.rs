@@ -1,4 +1,6 @@
 pub mod cli;
+pub mod config;
 pub mod error;
+pub mod export;
 pub mod scraper;
 pub mod util;
@@ -1,31 +1,81 @@
+mod convert;
+mod dedup;
+mod driver;
+mod engine;
 mod image;
+mod journal;
+mod limiter;
+mod manifest;
+mod naming;
+mod pipeline;
+mod queue;
+mod retry;
+mod robots;
+mod sanitize;
+mod similarity;
 mod url;
 
-use std::process::Command;
+use std::sync::{Arc, Mutex};
 
+use ::url::Url;
 use async_trait::async_trait;
 use derive_getters::Getters;
-use thirtyfour::{DesiredCapabilities, WebDriver};
 
-use crate::error::*;
+use crate::{
+    error::*,
+    export::{ExportFormat, PackMode},
+};
 
+pub use self::convert::*;
+pub use self::dedup::*;
+pub use self::driver::*;
+pub use self::engine::*;
 pub use self::image::*;
+pub use self::journal::*;
+pub use self::limiter::*;
+pub use self::manifest::*;
+pub use self::naming::*;
+pub use self::pipeline::*;
+pub use self::queue::*;
+pub use self::retry::*;
+pub use self::robots::*;
+pub use self::sanitize::*;
+pub use self::similarity::*;
 pub use self::url::*;
 
-const DRIVER_PORT: &str = "9515";
-const DISABLE_CORS_EXTENSION: &str = "ext/disable-cors";
-
-static IS_DRIVER_STARTING: bool = false;
-
 #[async_trait]
 pub trait Scrape {
-    async fn scrape(&mut self, urls: &Vec<String>) -> ScrapeResult<()>;
+    // Pulls jobs from the context's work queue until it is drained
+    async fn scrape(&mut self) -> ScrapeResult<()>;
+}
+
+pub enum ScrapeMessage<T> {
+    Data(T),
+    // Sent once every asset of the page has been sent
+    PageDone(String),
 }
 
 #[derive(Getters, Clone)]
 pub struct ScrapeStrategy {
     number_of_windows: usize,
     dest_dir: String,
+    depth: usize,
+    resume: bool,
+    engine: EngineKind,
+    retry_policy: RetryPolicy,
+    rate_limit: RateLimit,
+    respect_robots: bool,
+    robots_user_agent: String,
+    driver: DriverConfig,
+    browser: BrowserOptions,
+    dedup: Option<DedupMode>,
+    dedup_index: Option<String>,
+    url_format: UrlOutputFormat,
+    conversion: ImageConversion,
+    image_name: ImageNameTemplate,
+    pack: PackMode,
+    comic_info: bool,
+    exports: Vec<ExportFormat>,
 }
 
 impl Default for ScrapeStrategy {
@@ -33,6 +83,23 @@ impl Default for ScrapeStrategy {
         Self {
             number_of_windows: 1,
             dest_dir: String::from("download/"),
+            depth: 0,
+            resume: false,
+            engine: EngineKind::Browser,
+            retry_policy: RetryPolicy::default(),
+            rate_limit: RateLimit::default(),
+            respect_robots: false,
+            robots_user_agent: String::from("scraper"),
+            driver: DriverConfig::default(),
+            browser: BrowserOptions::default(),
+            dedup: None,
+            dedup_index: None,
+            url_format: UrlOutputFormat::default(),
+            conversion: ImageConversion::default(),
+            image_name: ImageNameTemplate::default(),
+            pack: PackMode::default(),
+            comic_info: false,
+            exports: vec![],
         }
     }
 }
@@ -51,44 +118,273 @@ impl ScrapeStrategy {
 
         self
     }
+
+    pub fn set_depth(&mut self, depth: usize) -> &mut Self {
+        self.depth = depth;
+
+        self
+    }
+
+    pub fn set_resume(&mut self, resume: bool) -> &mut Self {
+        self.resume = resume;
+
+        self
+    }
+
+    pub fn set_engine(&mut self, engine: EngineKind) -> &mut Self {
+        self.engine = engine;
+
+        self
+    }
+
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = policy;
+
+        self
+    }
+
+    pub fn set_rate_limit(&mut self, rate_limit: RateLimit) -> &mut Self {
+        self.rate_limit = rate_limit;
+
+        self
+    }
+
+    pub fn set_respect_robots(&mut self, respect: bool) -> &mut Self {
+        self.respect_robots = respect;
+
+        self
+    }
+
+    pub fn set_robots_user_agent(&mut self, user_agent: String) -> &mut Self {
+        if !user_agent.is_empty() {
+            self.robots_user_agent = user_agent;
+        }
+
+        self
+    }
+
+    pub fn set_driver(&mut self, driver: DriverConfig) -> &mut Self {
+        self.driver = driver;
+
+        self
+    }
+
+    pub fn set_browser_options(&mut self, options: BrowserOptions) -> &mut Self {
+        self.browser = options;
+
+        self
+    }
+
+    pub fn set_dedup(&mut self, mode: DedupMode) -> &mut Self {
+        self.dedup = Some(mode);
+
+        self
+    }
+
+    // Hashes of saved images are kept in this file to deduplicate across runs
+    pub fn set_dedup_index(&mut self, path: String) -> &mut Self {
+        self.dedup_index = Some(path);
+
+        self
+    }
+
+    pub fn set_url_format(&mut self, format: UrlOutputFormat) -> &mut Self {
+        self.url_format = format;
+
+        self
+    }
+
+    pub fn set_conversion(&mut self, conversion: ImageConversion) -> &mut Self {
+        self.conversion = conversion;
+
+        self
+    }
+
+    pub fn set_image_name(&mut self, template: ImageNameTemplate) -> &mut Self {
+        self.image_name = template;
+
+        self
+    }
+
+    pub fn set_pack(&mut self, pack: PackMode) -> &mut Self {
+        self.pack = pack;
+
+        self
+    }
+
+    // Adds ComicInfo.xml metadata to CBZ archives
+    pub fn set_comic_info(&mut self, comic_info: bool) -> &mut Self {
+        self.comic_info = comic_info;
+
+        self
+    }
+
+    // Formats every image directory of the output is exported to once downloaded
+    pub fn replace_exports(&mut self, exports: Vec<ExportFormat>) -> &mut Self {
+        self.exports = exports;
+
+        self
+    }
+}
+
+#[derive(Getters, Clone)]
+pub struct ScrapeContext {
+    strategy: ScrapeStrategy,
+    journal: Arc<CrawlJournal>,
+    queue: WorkQueue,
+    limiter: Arc<HostLimiter>,
+    robots: Option<Arc<RobotsCache>>,
+    driver: Option<Arc<DriverService>>,
+    failures: Arc<Mutex<Vec<(String, ScrapeError)>>>,
 }
 
-fn start_driver() -> ScrapeResult<String> {
-    if IS_DRIVER_STARTING {
-        // Server URL that the driver is listening
-        return Ok(format!("http:/localhost:{}", DRIVER_PORT));
+impl ScrapeContext {
+    pub fn new(
+        strategy: ScrapeStrategy,
+        journal: Arc<CrawlJournal>,
+        driver: Option<Arc<DriverService>>,
+    ) -> ScrapeResult<Self> {
+        let limiter = Arc::new(HostLimiter::new(strategy.rate_limit().clone()));
+        let robots = match strategy.respect_robots {
+            true => Some(Arc::new(RobotsCache::new(&strategy.robots_user_agent)?)),
+            false => None,
+        };
+
+        Ok(Self {
+            strategy,
+            journal,
+            queue: WorkQueue::default(),
+            limiter,
+            robots,
+            driver,
+            failures: Arc::new(Mutex::new(vec![])),
+        })
     }
 
-    let mut cmd: Command;
+    // Checks robots.txt when it is respected, and applies the host's Crawl-delay
+    pub async fn is_allowed(&self, url: &str) -> bool {
+        let (robots, parsed_url) = match (&self.robots, Url::parse(url)) {
+            (Some(robots), Ok(parsed_url)) => (robots, parsed_url),
+            _ => return true,
+        };
+        let rules = robots.rules(&parsed_url, &self.limiter).await;
 
-    if cfg!(target_os = "linux") {
-        cmd = Command::new("driver/linux-chromedriver");
-    } else if cfg!(target_os = "windows") {
-        cmd = Command::new("driver/win32-chromedriver");
-    } else if cfg!(target_os = "macos") {
-        cmd = Command::new("driver/mac_arm64-chromedriver");
-    } else {
-        return Err(ScrapeError::IncompatibleError(String::from(
-            "This feature is not yet available in your operating system",
-        )));
+        if let Some(delay) = rules.crawl_delay() {
+            self.limiter.set_host_delay(url, delay);
+        }
+
+        rules.is_allowed(&parsed_url)
+    }
+
+    // Loads the page, every attempt is throttled by the host's rate limit
+    pub async fn goto(&self, engine: &mut dyn Engine, url: &str) -> ScrapeResult<()> {
+        let mut attempt = 1;
+
+        loop {
+            self.limiter.throttle(url, true).await;
+
+            match engine.goto(url).await {
+                Ok(_) => return Ok(()),
+                Err(err) => self.strategy.retry_policy.wait(url, err, attempt).await?,
+            }
+
+            attempt += 1;
+        }
+    }
+
+    pub async fn fetch(&self, engine: &dyn Engine, src: &str) -> ScrapeResult<(String, Vec<u8>)> {
+        let mut attempt = 1;
+
+        loop {
+            self.limiter.throttle(src, false).await;
+
+            match engine.fetch(src).await {
+                Ok(resource) => return Ok(resource),
+                Err(err) => self.strategy.retry_policy.wait(src, err, attempt).await?,
+            }
+
+            attempt += 1;
+        }
     }
 
-    if cmd.arg(format!("--port={DRIVER_PORT}")).spawn().is_err() {
-        return Err(ScrapeError::CmdError(String::from(
-            "Unable to start driver",
-        )));
+    pub fn report_failure(&self, url: &str, err: ScrapeError) {
+        println!("Failed to scrape {url}: {err}");
+        self.failures.lock().unwrap().push((String::from(url), err));
     }
 
-    Ok(format!("http:/localhost:{}", DRIVER_PORT))
+    pub fn take_failures(&self) -> Vec<(String, ScrapeError)> {
+        std::mem::take(&mut *self.failures.lock().unwrap())
+    }
 }
 
-async fn new_driver() -> ScrapeResult<WebDriver> {
-    let mut caps = DesiredCapabilities::chrome();
-    caps.add_chrome_arg(format!("--load-extension={}", DISABLE_CORS_EXTENSION).as_str())
-        .unwrap();
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    pub(crate) enum Route {
+        Content(&'static str, Vec<u8>),
+        Redirect(&'static str),
+    }
+
+    // Serves the routes on a local port, returns its base url and the requested paths
+    pub(crate) async fn serve(
+        routes: Vec<(&'static str, Route)>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let routes = Arc::new(routes.into_iter().collect::<HashMap<_, _>>());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let requests_clone = requests.clone();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let routes = routes.clone();
+                let requests = requests_clone.clone();
+
+                tokio::spawn(async move {
+                    let mut request = vec![];
+                    let mut buffer = [0; 1024];
+
+                    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                        match stream.read(&mut buffer).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(read) => request.extend_from_slice(&buffer[..read]),
+                        }
+                    }
+
+                    let request = String::from_utf8_lossy(&request).to_string();
+                    let path = request.split(' ').nth(1).unwrap_or("/").to_string();
+                    let (head, body) = match routes.get(path.as_str()) {
+                        Some(Route::Content(mime_type, body)) => {
+                            (format!("200 OK\r\nContent-Type: {mime_type}"), body.clone())
+                        }
+                        Some(Route::Redirect(location)) => {
+                            (format!("302 Found\r\nLocation: {location}"), vec![])
+                        }
+                        None => (String::from("404 Not Found"), vec![]),
+                    };
+
+                    requests.lock().unwrap().push(path);
+
+                    let response = format!(
+                        "HTTP/1.1 {head}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    );
+
+                    let _ = stream.write_all(response.as_bytes()).await;
+                    let _ = stream.write_all(&body).await;
+                });
+            }
+        });
 
-    match WebDriver::new(start_driver()?.as_str(), caps).await {
-        Ok(driver) => Ok(driver),
-        Err(err) => Err(ScrapeError::WebDriverError(err)),
+        (base, requests)
     }
 }
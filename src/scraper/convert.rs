@@ -0,0 +1,258 @@
+use std::{fmt::Display, io::Cursor, str::FromStr};
+
+use ::image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    imageops::FilterType,
+    DynamicImage, ExtendedColorType, ImageEncoder, ImageFormat, ImageReader, Rgb, RgbImage,
+};
+use derive_getters::Getters;
+
+use crate::error::{ScrapeError, ScrapeResult};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ConvertFormat {
+    Jpeg,
+    Png,
+}
+
+impl Display for ConvertFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertFormat::Jpeg => write!(f, "jpeg"),
+            ConvertFormat::Png => write!(f, "png"),
+        }
+    }
+}
+
+impl FromStr for ConvertFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jpeg" | "jpg" => Ok(Self::Jpeg),
+            "png" => Ok(Self::Png),
+            _ => Err("Unsupported conversion format"),
+        }
+    }
+}
+
+impl ConvertFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ConvertFormat::Jpeg => "image/jpeg",
+            ConvertFormat::Png => "image/png",
+        }
+    }
+
+    fn image_format(&self) -> ImageFormat {
+        match self {
+            ConvertFormat::Jpeg => ImageFormat::Jpeg,
+            ConvertFormat::Png => ImageFormat::Png,
+        }
+    }
+}
+
+// Transcoding and downscaling applied to downloaded images before they are saved
+#[derive(Getters, Clone, Debug)]
+pub struct ImageConversion {
+    format: Option<ConvertFormat>,
+    // JPEG quality from 1 to 100
+    quality: u8,
+    // Longest side in pixels, larger images are scaled down keeping their ratio
+    max_dimension: Option<u32>,
+}
+
+impl Default for ImageConversion {
+    fn default() -> Self {
+        Self {
+            format: None,
+            quality: 90,
+            max_dimension: None,
+        }
+    }
+}
+
+impl ImageConversion {
+    pub fn set_format(&mut self, format: ConvertFormat) -> &mut Self {
+        self.format = Some(format);
+
+        self
+    }
+
+    pub fn set_quality(&mut self, quality: u8) -> &mut Self {
+        self.quality = quality.clamp(1, 100);
+
+        self
+    }
+
+    pub fn set_max_dimension(&mut self, max_dimension: u32) -> &mut Self {
+        if max_dimension > 0 {
+            self.max_dimension = Some(max_dimension);
+        }
+
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.format.is_some() || self.max_dimension.is_some()
+    }
+
+    // Returns the new MIME type and content, None when the image is already fine.
+    // Formats the decoder does not support (SVG, AVIF) cannot be converted
+    pub fn apply(&self, content: &[u8]) -> ScrapeResult<Option<(String, Vec<u8>)>> {
+        let reader = ImageReader::new(Cursor::new(content))
+            .with_guessed_format()
+            .map_err(ScrapeError::IoError)?;
+        let source_format = reader.format();
+        let target = match (self.format, source_format) {
+            (Some(format), _) => format,
+            (None, Some(ImageFormat::Jpeg)) => ConvertFormat::Jpeg,
+            // Resized images keep their format when it can be encoded, PNG otherwise
+            (None, _) => ConvertFormat::Png,
+        };
+        let (width, height) = reader
+            .into_dimensions()
+            .map_err(|err| ScrapeError::DecodeError(err.to_string()))?;
+        let too_large = self
+            .max_dimension
+            .is_some_and(|max| width.max(height) > max);
+
+        // Re-encoding an image already in the target format only loses quality
+        if !too_large && source_format == Some(target.image_format()) {
+            return Ok(None);
+        }
+
+        let mut image = ImageReader::new(Cursor::new(content))
+            .with_guessed_format()
+            .map_err(ScrapeError::IoError)?
+            .decode()
+            .map_err(|err| ScrapeError::DecodeError(err.to_string()))?;
+
+        if let (true, Some(max)) = (too_large, self.max_dimension) {
+            image = image.resize(max, max, FilterType::Lanczos3);
+        }
+
+        let mut output = vec![];
+        let result = match target {
+            ConvertFormat::Jpeg => {
+                let rgb = flatten(&image);
+
+                JpegEncoder::new_with_quality(&mut output, self.quality).write_image(
+                    rgb.as_raw(),
+                    rgb.width(),
+                    rgb.height(),
+                    ExtendedColorType::Rgb8,
+                )
+            }
+            ConvertFormat::Png => {
+                let rgba = image.to_rgba8();
+
+                PngEncoder::new(&mut output).write_image(
+                    rgba.as_raw(),
+                    rgba.width(),
+                    rgba.height(),
+                    ExtendedColorType::Rgba8,
+                )
+            }
+        };
+
+        result.map_err(|err| ScrapeError::DecodeError(err.to_string()))?;
+
+        Ok(Some((String::from(target.mime_type()), output)))
+    }
+}
+
+// JPEG has no alpha channel, transparent pixels are drawn over white
+fn flatten(image: &DynamicImage) -> RgbImage {
+    let rgba = image.to_rgba8();
+
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+
+        Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+        let mut content = Cursor::new(vec![]);
+
+        RgbImage::from_fn(width, height, |x, y| Rgb([x as u8, y as u8, 0]))
+            .write_to(&mut content, format)
+            .unwrap();
+
+        content.into_inner()
+    }
+
+    fn read(content: &[u8]) -> (Option<ImageFormat>, (u32, u32)) {
+        let reader = ImageReader::new(Cursor::new(content))
+            .with_guessed_format()
+            .unwrap();
+
+        (reader.format(), reader.into_dimensions().unwrap())
+    }
+
+    #[test]
+    fn every_format_is_converted_to_the_target() {
+        let sources = [
+            ImageFormat::Png,
+            ImageFormat::Jpeg,
+            ImageFormat::Gif,
+            ImageFormat::Bmp,
+        ];
+
+        for target in [ConvertFormat::Jpeg, ConvertFormat::Png] {
+            let mut conversion = ImageConversion::default();
+
+            conversion.set_format(target);
+
+            for source in sources {
+                let converted = conversion.apply(&encode(30, 20, source)).unwrap();
+
+                if source == target.image_format() {
+                    assert_eq!(converted, None);
+                    continue;
+                }
+
+                let (mime_type, content) = converted.unwrap();
+
+                assert_eq!(mime_type, target.mime_type());
+                assert_eq!(read(&content), (Some(target.image_format()), (30, 20)));
+            }
+        }
+    }
+
+    #[test]
+    fn large_images_are_scaled_down_in_their_format() {
+        let mut conversion = ImageConversion::default();
+
+        conversion.set_max_dimension(10);
+
+        let (mime_type, content) = conversion
+            .apply(&encode(40, 20, ImageFormat::Jpeg))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(mime_type, "image/jpeg");
+        assert_eq!(read(&content), (Some(ImageFormat::Jpeg), (10, 5)));
+        assert_eq!(
+            conversion.apply(&encode(10, 8, ImageFormat::Png)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn unsupported_formats_cannot_be_converted() {
+        let mut conversion = ImageConversion::default();
+
+        conversion.set_format(ConvertFormat::Png);
+
+        assert!(conversion
+            .apply(b"<svg width=\"10\" height=\"10\"/>")
+            .is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs::{self, File},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{ScrapeError, ScrapeResult};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DedupMode {
+    // Duplicates are not written at all
+    Skip,
+    // Duplicates are hard links to the first copy
+    Link,
+}
+
+impl Display for DedupMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DedupMode::Skip => write!(f, "skip"),
+            DedupMode::Link => write!(f, "link"),
+        }
+    }
+}
+
+impl FromStr for DedupMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "link" => Ok(Self::Link),
+            _ => Err("Unsupported dedup mode"),
+        }
+    }
+}
+
+pub fn content_hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+// Path of the first saved copy of each content hash, one `<hash>\t<path>` per line
+// when persisted so that later runs also recognize the files
+#[derive(Default)]
+pub struct ContentIndex {
+    file: Option<File>,
+    paths: HashMap<String, PathBuf>,
+}
+
+impl ContentIndex {
+    pub fn open(path: &str) -> ScrapeResult<Self> {
+        let mut index = Self::default();
+
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent).map_err(ScrapeError::IoError)?;
+        }
+
+        if Path::new(path).is_file() {
+            let f = File::open(path).map_err(ScrapeError::IoError)?;
+
+            for line in BufReader::new(f).lines().map_while(Result::ok) {
+                if let Some((hash, file)) = line.split_once('\t') {
+                    index.paths.insert(String::from(hash), PathBuf::from(file));
+                }
+            }
+        }
+
+        index.file = Some(
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(ScrapeError::IoError)?,
+        );
+
+        Ok(index)
+    }
+
+    // Files removed since they were indexed are not duplicates anymore
+    pub fn get(&self, hash: &str) -> Option<&PathBuf> {
+        self.paths.get(hash).filter(|path| path.is_file())
+    }
+
+    pub fn insert(&mut self, hash: String, path: PathBuf) {
+        // Absolute so that the index stays valid from another working directory
+        let path = fs::canonicalize(&path).unwrap_or(path);
+
+        if let Some(f) = self.file.as_mut() {
+            if writeln!(f, "{}\t{}", hash, path.display()).is_err() {
+                println!("Failed to write index entry: {hash}");
+            }
+        }
+
+        self.paths.insert(hash, path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn index_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("scraper-dedup-{name}-{}", std::process::id()));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn index_finds_duplicates_but_not_distinct_content() {
+        let dir = index_dir("duplicates");
+        let file = dir.join("1.png");
+        let mut index = ContentIndex::default();
+
+        fs::write(&file, b"first").unwrap();
+        index.insert(content_hash(b"first"), file.clone());
+
+        assert_eq!(content_hash(b"first"), content_hash(b"first"));
+        assert_eq!(
+            index.get(&content_hash(b"first")),
+            Some(&fs::canonicalize(&file).unwrap())
+        );
+        assert_eq!(index.get(&content_hash(b"second")), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn persisted_index_is_read_back_without_removed_files() {
+        let dir = index_dir("persisted");
+        let path = dir.join("index").to_string_lossy().into_owned();
+        let kept = dir.join("1.png");
+        let removed = dir.join("2.png");
+
+        fs::write(&kept, b"kept").unwrap();
+        fs::write(&removed, b"removed").unwrap();
+
+        {
+            let mut index = ContentIndex::open(&path).unwrap();
+
+            index.insert(content_hash(b"kept"), kept.clone());
+            index.insert(content_hash(b"removed"), removed.clone());
+        }
+
+        fs::remove_file(&removed).unwrap();
+
+        let index = ContentIndex::open(&path).unwrap();
+
+        assert!(index.get(&content_hash(b"kept")).is_some());
+        assert_eq!(index.get(&content_hash(b"removed")), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
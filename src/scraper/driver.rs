@@ -0,0 +1,182 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::Mutex,
+    time::Duration,
+};
+
+use derive_getters::Getters;
+use tokio::time::{sleep, Instant};
+
+use crate::error::{ScrapeError, ScrapeResult};
+
+use super::BrowserKind;
+
+const CHROMEDRIVER_PORT: u16 = 9515;
+const GECKODRIVER_PORT: u16 = 4444;
+const DRIVER_STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Getters, Clone, Default)]
+pub struct DriverConfig {
+    binary: Option<String>,
+    port: Option<u16>,
+    url: Option<String>,
+}
+
+impl DriverConfig {
+    pub fn set_binary(&mut self, path: String) -> &mut Self {
+        self.binary = Some(path);
+
+        self
+    }
+
+    pub fn set_port(&mut self, port: u16) -> &mut Self {
+        self.port = Some(port);
+
+        self
+    }
+
+    // Connects to an already running WebDriver endpoint instead of starting one
+    pub fn set_url(&mut self, url: String) -> &mut Self {
+        self.url = Some(url);
+
+        self
+    }
+
+    fn default_binary(kind: BrowserKind) -> ScrapeResult<String> {
+        let platform = if cfg!(target_os = "linux") {
+            "linux"
+        } else if cfg!(target_os = "windows") {
+            "win32"
+        } else if cfg!(target_os = "macos") {
+            "mac_arm64"
+        } else {
+            return Err(ScrapeError::IncompatibleError(String::from(
+                "This feature is not yet available in your operating system",
+            )));
+        };
+        let driver = match kind {
+            BrowserKind::Chrome => "chromedriver",
+            BrowserKind::Firefox => "geckodriver",
+        };
+
+        Ok(format!("driver/{platform}-{driver}"))
+    }
+
+    // Binary started for the browser when no remote url is configured
+    pub fn resolve_binary(&self, kind: BrowserKind) -> ScrapeResult<String> {
+        match &self.binary {
+            Some(binary) => Ok(binary.clone()),
+            None => Self::default_binary(kind),
+        }
+    }
+
+    // Where the binary would be started from, bare names are looked up in PATH like a shell does
+    pub fn find_binary(&self, kind: BrowserKind) -> ScrapeResult<Option<PathBuf>> {
+        let binary = self.resolve_binary(kind)?;
+        let path = Path::new(&binary);
+
+        if path.components().count() > 1 {
+            return Ok(path.is_file().then(|| path.to_path_buf()));
+        }
+
+        let names = match cfg!(target_os = "windows") {
+            true => vec![binary.clone(), format!("{binary}.exe")],
+            false => vec![binary.clone()],
+        };
+
+        Ok(env::var_os("PATH").and_then(|paths| {
+            env::split_paths(&paths)
+                .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+                .find(|candidate| candidate.is_file())
+        }))
+    }
+
+    fn default_port(kind: BrowserKind) -> u16 {
+        match kind {
+            BrowserKind::Chrome => CHROMEDRIVER_PORT,
+            BrowserKind::Firefox => GECKODRIVER_PORT,
+        }
+    }
+}
+
+// WebDriver server shared by every worker, the managed process is killed when dropped
+pub struct DriverService {
+    url: String,
+    process: Mutex<Option<Child>>,
+}
+
+impl DriverService {
+    pub async fn start(config: &DriverConfig, kind: BrowserKind) -> ScrapeResult<Self> {
+        if let Some(url) = &config.url {
+            return Ok(Self {
+                url: url.trim_end_matches('/').to_string(),
+                process: Mutex::new(None),
+            });
+        }
+
+        let binary = config.resolve_binary(kind)?;
+        let port = config.port.unwrap_or(DriverConfig::default_port(kind));
+        let process = Command::new(&binary)
+            .arg(format!("--port={port}"))
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|err| {
+                ScrapeError::CmdError(format!("Unable to start driver {binary}: {err}"))
+            })?;
+        let service = Self {
+            url: format!("http://localhost:{port}"),
+            process: Mutex::new(Some(process)),
+        };
+
+        service.wait_until_ready().await?;
+
+        Ok(service)
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    async fn wait_until_ready(&self) -> ScrapeResult<()> {
+        let deadline = Instant::now() + DRIVER_STARTUP_TIMEOUT;
+        let status_url = format!("{}/status", self.url);
+
+        while Instant::now() < deadline {
+            if let Some(process) = self.process.lock().unwrap().as_mut() {
+                if let Ok(Some(status)) = process.try_wait() {
+                    return Err(ScrapeError::CmdError(format!(
+                        "Driver exited while starting: {status}"
+                    )));
+                }
+            }
+
+            if let Ok(response) = reqwest::get(&status_url).await {
+                if response.status().is_success() {
+                    return Ok(());
+                }
+            }
+
+            sleep(Duration::from_millis(200)).await;
+        }
+
+        Err(ScrapeError::TimeoutError(format!(
+            "Driver is not listening on {}",
+            self.url
+        )))
+    }
+
+    pub fn stop(&self) {
+        if let Some(mut process) = self.process.lock().unwrap().take() {
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+    }
+}
+
+impl Drop for DriverService {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,101 @@
+mod browser;
+mod http;
+
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use async_trait::async_trait;
+use derive_getters::Getters;
+use url::Url;
+
+use crate::error::{ScrapeError, ScrapeResult};
+
+use super::ScrapeContext;
+
+pub use self::browser::*;
+pub use self::http::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EngineKind {
+    Browser,
+    Http,
+}
+
+impl Display for EngineKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EngineKind::Browser => write!(f, "browser"),
+            EngineKind::Http => write!(f, "http"),
+        }
+    }
+}
+
+impl FromStr for EngineKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "browser" => Ok(Self::Browser),
+            "http" => Ok(Self::Http),
+            _ => Err("Unsupported engine"),
+        }
+    }
+}
+
+// Snapshot of an HTML element, sizes are None when the engine cannot tell them
+#[derive(Debug, Getters)]
+pub struct PageElement {
+    attrs: HashMap<String, String>,
+    // Text content with whitespace collapsed
+    text: String,
+    width: Option<usize>,
+    height: Option<usize>,
+}
+
+impl PageElement {
+    pub fn attr(&self, name: &str) -> Option<&String> {
+        self.attrs.get(name)
+    }
+}
+
+#[async_trait]
+pub trait Engine: Send + Sync {
+    async fn goto(&mut self, url: &str) -> ScrapeResult<()>;
+
+    async fn title(&self) -> ScrapeResult<String>;
+
+    // Url of the current page after redirects, relative urls of the page resolve against it
+    async fn current_url(&self) -> ScrapeResult<Url>;
+
+    // Elements with the given tag name in document order, with the requested attributes
+    async fn find_all(&self, tag: &str, attrs: &[&str]) -> ScrapeResult<Vec<PageElement>>;
+
+    // Downloads a resource referenced by the current page, returns its MIME type and content
+    async fn fetch(&self, src: &str) -> ScrapeResult<(String, Vec<u8>)>;
+
+    async fn quit(self: Box<Self>) -> ScrapeResult<()>;
+}
+
+// Titles of the interstitial pages anti-bot services show before the content
+const CHALLENGE_TITLES: [&str; 3] = [
+    "Just a moment...",
+    "Attention Required! | Cloudflare",
+    "DDoS-Guard",
+];
+
+pub fn is_challenge_title(title: &str) -> bool {
+    CHALLENGE_TITLES.iter().any(|t| title.trim() == *t)
+}
+
+pub async fn new_engine(context: &ScrapeContext) -> ScrapeResult<Box<dyn Engine>> {
+    match context.strategy().engine() {
+        EngineKind::Browser => match context.driver() {
+            Some(driver) => Ok(Box::new(
+                BrowserEngine::new(driver.url(), context.strategy().browser()).await?,
+            )),
+            None => Err(ScrapeError::CmdError(String::from(
+                "Browser engine requires a running driver",
+            ))),
+        },
+        EngineKind::Http => Ok(Box::new(HttpEngine::new(context.strategy().browser())?)),
+    }
+}
@@ -0,0 +1,450 @@
+use std::{collections::HashMap, fmt::Display, fs, str::FromStr};
+
+use async_trait::async_trait;
+use base64::decode;
+use dataurl::DataUrl;
+use derive_getters::Getters;
+use serde_json::{json, Value};
+use thirtyfour::{
+    common::capabilities::firefox::FirefoxPreferences, extensions::addons::firefox::FirefoxTools,
+    prelude::WebDriverError, DesiredCapabilities, WebDriver,
+};
+use url::Url;
+
+use crate::error::{ScrapeError, ScrapeResult};
+
+use super::{is_challenge_title, Engine, PageElement};
+
+const DISABLE_CORS_EXTENSION: &str = "ext/disable-cors";
+const DISABLE_CORS_FIREFOX_EXTENSION: &str = "ext/disable-cors-firefox";
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum BrowserKind {
+    #[default]
+    Chrome,
+    Firefox,
+}
+
+impl Display for BrowserKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BrowserKind::Chrome => write!(f, "chrome"),
+            BrowserKind::Firefox => write!(f, "firefox"),
+        }
+    }
+}
+
+impl BrowserKind {
+    // Unpacked extension disabling CORS for this browser
+    pub fn extension(&self) -> &'static str {
+        match self {
+            BrowserKind::Chrome => DISABLE_CORS_EXTENSION,
+            BrowserKind::Firefox => DISABLE_CORS_FIREFOX_EXTENSION,
+        }
+    }
+}
+
+impl FromStr for BrowserKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chrome" => Ok(Self::Chrome),
+            "firefox" => Ok(Self::Firefox),
+            _ => Err("Unsupported browser"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WindowSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for WindowSize {
+    type Err = &'static str;
+
+    // Format: <width>x<height>
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .split_once('x')
+            .ok_or("Window size must be <width>x<height>")?;
+
+        match (width.trim().parse(), height.trim().parse()) {
+            (Ok(width), Ok(height)) => Ok(Self { width, height }),
+            _ => Err("Window size must be <width>x<height>"),
+        }
+    }
+}
+
+#[derive(Getters, Clone, Default)]
+pub struct BrowserOptions {
+    kind: BrowserKind,
+    headless: bool,
+    window_size: Option<WindowSize>,
+    user_agent: Option<String>,
+    proxy: Option<String>,
+    user_data_dir: Option<String>,
+    args: Vec<String>,
+}
+
+impl BrowserOptions {
+    pub fn set_kind(&mut self, kind: BrowserKind) -> &mut Self {
+        self.kind = kind;
+
+        self
+    }
+
+    pub fn set_headless(&mut self, headless: bool) -> &mut Self {
+        self.headless = headless;
+
+        self
+    }
+
+    pub fn set_window_size(&mut self, size: WindowSize) -> &mut Self {
+        self.window_size = Some(size);
+
+        self
+    }
+
+    pub fn set_user_agent(&mut self, user_agent: String) -> &mut Self {
+        self.user_agent = Some(user_agent);
+
+        self
+    }
+
+    pub fn set_proxy(&mut self, proxy: String) -> &mut Self {
+        self.proxy = Some(proxy);
+
+        self
+    }
+
+    pub fn set_user_data_dir(&mut self, path: String) -> &mut Self {
+        self.user_data_dir = Some(path);
+
+        self
+    }
+
+    pub fn add_arg(&mut self, arg: String) -> &mut Self {
+        if !self.args.contains(&arg) {
+            self.args.push(arg);
+        }
+
+        self
+    }
+
+    fn chrome_args(&self) -> Vec<String> {
+        let mut args = vec![format!("--load-extension={}", DISABLE_CORS_EXTENSION)];
+
+        if self.headless {
+            // New headless mode still loads extensions
+            args.push(String::from("--headless=new"));
+        }
+
+        if let Some(size) = self.window_size {
+            args.push(format!("--window-size={},{}", size.width, size.height));
+        }
+
+        if let Some(user_agent) = &self.user_agent {
+            args.push(format!("--user-agent={user_agent}"));
+        }
+
+        if let Some(proxy) = &self.proxy {
+            args.push(format!("--proxy-server={proxy}"));
+        }
+
+        if let Some(user_data_dir) = &self.user_data_dir {
+            args.push(format!("--user-data-dir={user_data_dir}"));
+        }
+
+        args.extend(self.args.iter().cloned());
+
+        args
+    }
+
+    fn firefox_args(&self) -> Vec<String> {
+        let mut args = vec![];
+
+        if self.headless {
+            args.push(String::from("-headless"));
+        }
+
+        if let Some(size) = self.window_size {
+            args.push(format!("--width={}", size.width));
+            args.push(format!("--height={}", size.height));
+        }
+
+        if let Some(user_data_dir) = &self.user_data_dir {
+            args.push(String::from("-profile"));
+            args.push(user_data_dir.clone());
+        }
+
+        args.extend(self.args.iter().cloned());
+
+        args
+    }
+
+    // Firefox has no command line flags for these, they are profile preferences
+    fn firefox_preferences(&self) -> ScrapeResult<FirefoxPreferences> {
+        let mut prefs = FirefoxPreferences::new();
+
+        if let Some(user_agent) = &self.user_agent {
+            prefs
+                .set_user_agent(user_agent.clone())
+                .map_err(ScrapeError::WebDriverError)?;
+        }
+
+        if let Some(proxy) = &self.proxy {
+            let url = Url::parse(proxy)
+                .map_err(|err| ScrapeError::CmdError(format!("Invalid proxy {proxy}: {err}")))?;
+            let host = url.host_str().unwrap_or_default().to_string();
+            let port = url.port_or_known_default().unwrap_or(80);
+            let mut values = vec![("network.proxy.type", json!(1))];
+
+            match url.scheme() {
+                "socks4" | "socks5" => {
+                    let version = if url.scheme() == "socks4" { 4 } else { 5 };
+
+                    values.push(("network.proxy.socks", json!(host)));
+                    values.push(("network.proxy.socks_port", json!(port)));
+                    values.push(("network.proxy.socks_version", json!(version)));
+                    values.push(("network.proxy.socks_remote_dns", json!(true)));
+                }
+                _ => {
+                    values.push(("network.proxy.http", json!(host)));
+                    values.push(("network.proxy.http_port", json!(port)));
+                    values.push(("network.proxy.ssl", json!(host)));
+                    values.push(("network.proxy.ssl_port", json!(port)));
+                }
+            }
+
+            for (key, value) in values {
+                prefs.set(key, value).map_err(ScrapeError::WebDriverError)?;
+            }
+        }
+
+        Ok(prefs)
+    }
+}
+
+async fn new_driver(server_url: &str, options: &BrowserOptions) -> ScrapeResult<WebDriver> {
+    match options.kind {
+        BrowserKind::Chrome => {
+            let mut caps = DesiredCapabilities::chrome();
+
+            for arg in options.chrome_args() {
+                caps.add_chrome_arg(&arg)
+                    .map_err(ScrapeError::WebDriverError)?;
+            }
+
+            WebDriver::new(server_url, caps)
+                .await
+                .map_err(ScrapeError::WebDriverError)
+        }
+        BrowserKind::Firefox => {
+            let mut caps = DesiredCapabilities::firefox();
+
+            for arg in options.firefox_args() {
+                caps.add_firefox_arg(&arg)
+                    .map_err(ScrapeError::WebDriverError)?;
+            }
+
+            caps.set_preferences(options.firefox_preferences()?)
+                .map_err(ScrapeError::WebDriverError)?;
+
+            let driver = WebDriver::new(server_url, caps)
+                .await
+                .map_err(ScrapeError::WebDriverError)?;
+
+            install_firefox_extension(&driver).await;
+
+            Ok(driver)
+        }
+    }
+}
+
+// Firefox cannot load unpacked extensions from the command line, it is installed per session
+async fn install_firefox_extension(driver: &WebDriver) {
+    // geckodriver only accepts absolute paths
+    let path = match fs::canonicalize(DISABLE_CORS_FIREFOX_EXTENSION) {
+        Ok(path) => path.to_string_lossy().to_string(),
+        Err(err) => {
+            println!("Unable to find {DISABLE_CORS_FIREFOX_EXTENSION}: {err}");
+            return;
+        }
+    };
+
+    // Images can still be fetched from the same origin without it
+    if let Err(err) = FirefoxTools::new(driver.handle.clone())
+        .install_addon(&path, Some(true))
+        .await
+    {
+        println!("Unable to install {DISABLE_CORS_FIREFOX_EXTENSION}: {err}");
+    }
+}
+
+fn is_timeout(err: &WebDriverError) -> bool {
+    matches!(err, WebDriverError::Timeout(_)) || err.to_string().contains("timeout")
+}
+
+fn fetch_error(src: &str, err: WebDriverError) -> ScrapeError {
+    let message = err.to_string();
+
+    if is_timeout(&err) {
+        return ScrapeError::TimeoutError(message);
+    }
+
+    // Thrown by the fetch script when the response is not ok
+    match message
+        .split_once("HTTP status ")
+        .and_then(|(_, rest)| rest.get(..3))
+        .and_then(|status| status.parse().ok())
+    {
+        Some(status) => ScrapeError::StatusError(status, String::from(src)),
+        None => ScrapeError::FetchError(message),
+    }
+}
+
+pub struct BrowserEngine {
+    driver: WebDriver,
+}
+
+impl BrowserEngine {
+    pub async fn new(server_url: &str, options: &BrowserOptions) -> ScrapeResult<Self> {
+        Ok(Self {
+            driver: new_driver(server_url, options).await?,
+        })
+    }
+
+    // Format of data url: data:[<mediatype>][;base64],<data>
+    fn get_data(&self, data_url: String) -> ScrapeResult<(String, Vec<u8>)> {
+        let parsed = DataUrl::parse(data_url.as_str())
+            .map_err(|err| ScrapeError::DecodeError(format!("Invalid data url: {err:?}")))?;
+        let comma_index = data_url.find(',').unwrap_or(0);
+        // Skip comma and all characters before it
+        let data = data_url.chars().skip(comma_index + 1).collect::<String>();
+
+        match decode(data) {
+            Ok(content) => Ok((String::from(parsed.get_media_type()), content)),
+            Err(err) => Err(ScrapeError::DecodeError(err.to_string())),
+        }
+    }
+}
+
+#[async_trait]
+impl Engine for BrowserEngine {
+    async fn goto(&mut self, url: &str) -> ScrapeResult<()> {
+        self.driver
+            .goto(url)
+            .await
+            .map_err(|err| match is_timeout(&err) {
+                true => ScrapeError::TimeoutError(err.to_string()),
+                false => ScrapeError::NavigationError(err.to_string()),
+            })?;
+
+        match self.title().await {
+            Ok(title) if is_challenge_title(&title) => {
+                Err(ScrapeError::ChallengeError(String::from(url)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn title(&self) -> ScrapeResult<String> {
+        self.driver
+            .title()
+            .await
+            .map_err(ScrapeError::WebDriverError)
+    }
+
+    async fn current_url(&self) -> ScrapeResult<Url> {
+        self.driver
+            .current_url()
+            .await
+            .map_err(ScrapeError::WebDriverError)
+    }
+
+    async fn find_all(&self, tag: &str, attrs: &[&str]) -> ScrapeResult<Vec<PageElement>> {
+        // Read everything in a single round trip instead of one command per attribute
+        let result = self
+            .driver
+            .execute(
+                "const [tag, names] = arguments;
+                return Array.from(document.getElementsByTagName(tag)).map(el => ({
+                    attrs: Object.fromEntries(names.map(name => [name, el.getAttribute(name)])),
+                    text: (el.textContent || '').replace(/\\s+/g, ' ').trim(),
+                    width: typeof el.width === 'number' ? el.width : null,
+                    height: typeof el.height === 'number' ? el.height : null,
+                }));",
+                vec![json!(tag), json!(attrs)],
+            )
+            .await
+            .map_err(ScrapeError::WebDriverError)?;
+
+        let elements = match result.json() {
+            Value::Array(elements) => elements,
+            _ => return Ok(vec![]),
+        };
+
+        Ok(elements
+            .iter()
+            .map(|element| PageElement {
+                attrs: match &element["attrs"] {
+                    Value::Object(attrs) => attrs
+                        .iter()
+                        .filter_map(|(name, value)| {
+                            value.as_str().map(|v| (name.clone(), String::from(v)))
+                        })
+                        .collect(),
+                    _ => HashMap::new(),
+                },
+                text: element["text"]
+                    .as_str()
+                    .map(String::from)
+                    .unwrap_or_default(),
+                width: element["width"].as_u64().map(|v| v as usize),
+                height: element["height"].as_u64().map(|v| v as usize),
+            })
+            .collect())
+    }
+
+    async fn fetch(&self, src: &str) -> ScrapeResult<(String, Vec<u8>)> {
+        // Fetch inside the page so cookies and Cloudflare clearance are reused
+        let result = self
+            .driver
+            .execute(
+                "return fetch(arguments[0])
+                    .then(response => {
+                        if (!response.ok) {
+                            throw new Error('HTTP status ' + response.status);
+                        }
+
+                        return response.blob();
+                    })
+                    .then(blob => new Promise(callback => {
+                        let reader = new FileReader();
+                        reader.onload = function() {
+                            callback(this.result);
+                        };
+                        reader.readAsDataURL(blob);
+                    }))
+                    .then(data => data);",
+                vec![json!(src)],
+            )
+            .await
+            .map_err(|err| fetch_error(src, err))?;
+        let data_url = result
+            .convert()
+            .map_err(|err| ScrapeError::DecodeError(err.to_string()))?;
+
+        self.get_data(data_url)
+    }
+
+    async fn quit(self: Box<Self>) -> ScrapeResult<()> {
+        self.driver
+            .quit()
+            .await
+            .map_err(ScrapeError::WebDriverError)
+    }
+}
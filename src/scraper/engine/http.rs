@@ -0,0 +1,315 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use html::{Html, Selector};
+use reqwest::{header::CONTENT_TYPE, Client, Proxy, Response};
+use url::Url;
+
+use crate::error::{ScrapeError, ScrapeResult};
+
+use super::{BrowserOptions, Engine, PageElement};
+
+const USER_AGENT: &str = concat!("scraper/", env!("CARGO_PKG_VERSION"));
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn request_error(err: reqwest::Error, or_else: fn(String) -> ScrapeError) -> ScrapeError {
+    match err.is_timeout() {
+        true => ScrapeError::TimeoutError(err.to_string()),
+        false => or_else(err.to_string()),
+    }
+}
+
+fn check_status(response: Response) -> ScrapeResult<Response> {
+    let status = response.status();
+
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let url = response.url().to_string();
+    let is_challenge = response
+        .headers()
+        .get("cf-mitigated")
+        .is_some_and(|value| value == "challenge");
+
+    match is_challenge {
+        true => Err(ScrapeError::ChallengeError(url)),
+        false => Err(ScrapeError::StatusError(status.as_u16(), url)),
+    }
+}
+
+// Fetches pages without running their scripts, only suitable for static sites
+pub struct HttpEngine {
+    client: Client,
+    url: Option<Url>,
+    body: String,
+}
+
+impl HttpEngine {
+    // Only the user agent and proxy of the browser options apply to plain requests
+    pub fn new(options: &BrowserOptions) -> ScrapeResult<Self> {
+        let mut builder = Client::builder()
+            .user_agent(options.user_agent().as_deref().unwrap_or(USER_AGENT))
+            .timeout(REQUEST_TIMEOUT);
+
+        if let Some(proxy) = options.proxy() {
+            builder = builder.proxy(Proxy::all(proxy).map_err(ScrapeError::HttpError)?);
+        }
+
+        let client = builder.build().map_err(ScrapeError::HttpError)?;
+
+        Ok(Self {
+            client,
+            url: None,
+            body: String::new(),
+        })
+    }
+
+    fn select(&self, tag: &str, attrs: &[&str]) -> Vec<PageElement> {
+        let document = Html::parse_document(&self.body);
+        let selector = match Selector::parse(tag) {
+            Ok(selector) => selector,
+            Err(_) => return vec![],
+        };
+
+        document
+            .select(&selector)
+            .map(|element| {
+                let size = |name| element.value().attr(name).and_then(|v| v.parse().ok());
+
+                PageElement {
+                    attrs: attrs
+                        .iter()
+                        .filter_map(|&name| {
+                            element
+                                .value()
+                                .attr(name)
+                                .map(|v| (String::from(name), String::from(v)))
+                        })
+                        .collect(),
+                    text: element
+                        .text()
+                        .collect::<String>()
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    width: size("width"),
+                    height: size("height"),
+                }
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Engine for HttpEngine {
+    async fn goto(&mut self, url: &str) -> ScrapeResult<()> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|err| request_error(err, ScrapeError::NavigationError))
+            .and_then(check_status)?;
+
+        self.url = Some(response.url().clone());
+        self.body = response
+            .text()
+            .await
+            .map_err(|err| request_error(err, ScrapeError::NavigationError))?;
+
+        Ok(())
+    }
+
+    async fn title(&self) -> ScrapeResult<String> {
+        let document = Html::parse_document(&self.body);
+        let selector = Selector::parse("title").unwrap();
+
+        Ok(document
+            .select(&selector)
+            .flat_map(|title| title.text())
+            .collect::<String>()
+            .trim()
+            .to_string())
+    }
+
+    async fn current_url(&self) -> ScrapeResult<Url> {
+        self.url
+            .clone()
+            .ok_or_else(|| ScrapeError::NavigationError(String::from("No page loaded")))
+    }
+
+    async fn find_all(&self, tag: &str, attrs: &[&str]) -> ScrapeResult<Vec<PageElement>> {
+        Ok(self.select(tag, attrs))
+    }
+
+    async fn fetch(&self, src: &str) -> ScrapeResult<(String, Vec<u8>)> {
+        // Relative sources are resolved against the current page
+        let url = match &self.url {
+            Some(page_url) => page_url.join(src),
+            None => Url::parse(src),
+        }
+        .map_err(|err| ScrapeError::FetchError(format!("Invalid url {src}: {err}")))?;
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|err| request_error(err, ScrapeError::FetchError))
+            .and_then(check_status)?;
+        let mime_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.split(';').next().unwrap_or("").trim().to_string())
+            .unwrap_or_default();
+        let content = response
+            .bytes()
+            .await
+            .map_err(|err| request_error(err, ScrapeError::FetchError))?;
+
+        Ok((mime_type, content.to_vec()))
+    }
+
+    async fn quit(self: Box<Self>) -> ScrapeResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::Arc};
+
+    use ::image::{ImageFormat, RgbImage};
+    use tokio::sync::mpsc::channel;
+
+    use super::*;
+    use crate::scraper::{
+        tests::{serve, Route},
+        CrawlJournal, EngineKind, ImageMimeType, ImageScraper, Scrape, ScrapeContext,
+        ScrapeImageFilter, ScrapeMessage, ScrapeStrategy,
+    };
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut content = Cursor::new(vec![]);
+
+        RgbImage::from_fn(width, height, |x, y| ::image::Rgb([x as u8, y as u8, 0]))
+            .write_to(&mut content, ImageFormat::Png)
+            .unwrap();
+
+        content.into_inner()
+    }
+
+    fn chapter() -> Vec<(&'static str, Route)> {
+        let page = r#"<html><head><title> Chapter 1 </title></head><body>
+            <img src="../img/page.png" alt="Page 1" width="400" height="600">
+            <img src="/img/broken.jpg" class="ad">
+            <img src="icon.svg">
+            <a href="/chapter/2">Next   chapter</a>
+        </body></html>"#;
+
+        vec![
+            ("/start", Route::Redirect("/chapter/1")),
+            ("/chapter/1", Route::Content("text/html", page.into())),
+            ("/img/page.png", Route::Content("image/png", png(400, 600))),
+            (
+                "/img/broken.jpg",
+                Route::Content("image/jpeg", b"<html>Not found</html>".to_vec()),
+            ),
+            (
+                "/chapter/icon.svg",
+                Route::Content("image/svg+xml", b"<svg width=\"100%\"/>".to_vec()),
+            ),
+        ]
+    }
+
+    #[tokio::test]
+    async fn reads_the_page_after_redirects() {
+        let (base, _) = serve(chapter()).await;
+        let mut engine = HttpEngine::new(&BrowserOptions::default()).unwrap();
+
+        engine.goto(&format!("{base}/start")).await.unwrap();
+
+        assert_eq!(engine.title().await.unwrap(), "Chapter 1");
+        assert_eq!(
+            engine.current_url().await.unwrap().as_str(),
+            format!("{base}/chapter/1")
+        );
+
+        let images = engine.find_all("img", &["src", "alt"]).await.unwrap();
+
+        assert_eq!(images.len(), 3);
+        assert_eq!(images[0].attr("alt").unwrap(), "Page 1");
+        assert_eq!(
+            (*images[0].width(), *images[0].height()),
+            (Some(400), Some(600))
+        );
+        assert_eq!(*images[1].width(), None);
+
+        let links = engine.find_all("a", &["href"]).await.unwrap();
+
+        assert_eq!(links[0].text(), "Next chapter");
+    }
+
+    #[tokio::test]
+    async fn fetches_relative_sources_and_reports_statuses() {
+        let (base, _) = serve(chapter()).await;
+        let mut engine = HttpEngine::new(&BrowserOptions::default()).unwrap();
+
+        engine.goto(&format!("{base}/chapter/1")).await.unwrap();
+
+        let (mime_type, content) = engine.fetch("../img/page.png").await.unwrap();
+
+        assert_eq!(mime_type, "image/png");
+        assert_eq!(content, png(400, 600));
+        assert!(matches!(
+            engine.fetch("missing.png").await,
+            Err(ScrapeError::StatusError(404, _))
+        ));
+        assert!(matches!(
+            engine.goto(&format!("{base}/missing")).await,
+            Err(ScrapeError::StatusError(404, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn image_scraper_resolves_sources_and_skips_unknown_sizes() {
+        let (base, requests) = serve(chapter()).await;
+        let (tx, mut rx) = channel(10);
+        let mut strategy = ScrapeStrategy::default();
+        let mut filter = ScrapeImageFilter::default();
+
+        strategy.set_engine(EngineKind::Http);
+        filter
+            .replace_mime_types(vec![ImageMimeType::Any])
+            .set_min_width(1)
+            .set_min_height(1);
+
+        let context =
+            ScrapeContext::new(strategy, Arc::new(CrawlJournal::default()), None).unwrap();
+
+        context.queue().push(format!("{base}/start"), 0);
+        ImageScraper::new(tx, filter, context)
+            .scrape()
+            .await
+            .unwrap();
+
+        let mut images = vec![];
+
+        while let Some(message) = rx.recv().await {
+            if let ScrapeMessage::Data(image) = message {
+                images.push(image);
+            }
+        }
+
+        // The HTML error page and the SVG without size cannot be checked
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].src(), &format!("{base}/img/page.png"));
+        assert_eq!(images[0].mime_type(), "image/png");
+        assert_eq!(images[0].dimensions(), Some((400, 600)));
+        assert!(requests
+            .lock()
+            .unwrap()
+            .contains(&String::from("/chapter/icon.svg")));
+    }
+}
@@ -1,29 +1,55 @@
-use std::{fmt::Display, fs, str::FromStr, time::SystemTime};
+use std::{
+    fmt::Display,
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::LazyLock,
+};
 
 use async_trait::async_trait;
-use base64::decode;
-use chrono::{DateTime, Utc};
-use dataurl::DataUrl;
 use derive_getters::Getters;
-use thirtyfour::{fantoccini::error::CmdError, prelude::WebDriverError, By, WebDriver, WebElement};
+use regex::Regex;
 use tokio::sync::mpsc::Sender;
+use url::Url;
 
-use crate::error::ScrapeResult;
+use crate::error::{ScrapeError, ScrapeResult};
 
-use super::{new_driver, Scrape};
+use super::{
+    content_hash, new_engine, sanitize_component, CrawlJournal, Engine, ImageConversion,
+    ImageNameTemplate, NameValues, PageElement, PathSanitizer, PerceptualHash, Scrape,
+    ScrapeContext, ScrapeMessage, SimilarityFilter,
+};
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum ImageMimeType {
     Jpeg,
     Png,
+    Webp,
+    Gif,
+    Avif,
+    Svg,
+    Bmp,
+    Ico,
+    // `image/*`, accepts every image type
+    Any,
 }
 
+// Canonical MIME type and file extension of each type
+const MIME_TYPES: [(ImageMimeType, &str, &str); 8] = [
+    (ImageMimeType::Jpeg, "image/jpeg", "jpg"),
+    (ImageMimeType::Png, "image/png", "png"),
+    (ImageMimeType::Webp, "image/webp", "webp"),
+    (ImageMimeType::Gif, "image/gif", "gif"),
+    (ImageMimeType::Avif, "image/avif", "avif"),
+    (ImageMimeType::Svg, "image/svg+xml", "svg"),
+    (ImageMimeType::Bmp, "image/bmp", "bmp"),
+    (ImageMimeType::Ico, "image/x-icon", "ico"),
+];
+
 impl Display for ImageMimeType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ImageMimeType::Jpeg => write!(f, "image/jpeg"),
-            ImageMimeType::Png => write!(f, "image/png"),
-        }
+        write!(f, "{}", self.as_str())
     }
 }
 
@@ -31,57 +57,434 @@ impl FromStr for ImageMimeType {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "image/jpeg" => Ok(Self::Jpeg),
-            "image/png" => Ok(Self::Png),
+        match s.trim().to_lowercase().as_str() {
+            "image/jpeg" | "image/jpg" | "image/pjpeg" => Ok(Self::Jpeg),
+            "image/png" | "image/apng" => Ok(Self::Png),
+            "image/webp" => Ok(Self::Webp),
+            "image/gif" => Ok(Self::Gif),
+            "image/avif" => Ok(Self::Avif),
+            "image/svg+xml" | "image/svg" => Ok(Self::Svg),
+            "image/bmp" | "image/x-bmp" | "image/x-ms-bmp" => Ok(Self::Bmp),
+            "image/x-icon" | "image/vnd.microsoft.icon" | "image/ico" => Ok(Self::Ico),
+            "image/*" => Ok(Self::Any),
             _ => Err("Unsupported MIME type"),
         }
     }
 }
 
+impl ImageMimeType {
+    pub fn as_str(&self) -> &'static str {
+        MIME_TYPES
+            .iter()
+            .find(|(mime_type, _, _)| mime_type == self)
+            .map(|(_, name, _)| *name)
+            .unwrap_or("image/*")
+    }
+
+    pub fn extension(&self) -> Option<&'static str> {
+        MIME_TYPES
+            .iter()
+            .find(|(mime_type, _, _)| mime_type == self)
+            .map(|(_, _, extension)| *extension)
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "jpeg" | "jpe" => Some(Self::Jpeg),
+            extension => MIME_TYPES
+                .iter()
+                .find(|(_, _, ext)| *ext == extension)
+                .map(|(mime_type, _, _)| *mime_type),
+        }
+    }
+
+    // The wildcard accepts any `image/` type, even the ones without a variant
+    pub fn accepts(&self, media_type: &str) -> bool {
+        match self {
+            ImageMimeType::Any => media_type.trim().to_lowercase().starts_with("image/"),
+            mime_type => Self::from_str(media_type).is_ok_and(|t| t == *mime_type),
+        }
+    }
+}
+
+// Real type of the content from its magic bytes, servers often send a wrong
+// or generic Content-Type such as `application/octet-stream`
+pub fn sniff_mime_type(content: &[u8]) -> Option<ImageMimeType> {
+    let starts_with =
+        |offset: usize, magic: &[u8]| content.get(offset..offset + magic.len()) == Some(magic);
+
+    if starts_with(0, &[0xFF, 0xD8, 0xFF]) {
+        Some(ImageMimeType::Jpeg)
+    } else if starts_with(0, b"\x89PNG\r\n\x1A\n") {
+        Some(ImageMimeType::Png)
+    } else if starts_with(0, b"GIF87a") || starts_with(0, b"GIF89a") {
+        Some(ImageMimeType::Gif)
+    } else if starts_with(0, b"RIFF") && starts_with(8, b"WEBP") {
+        Some(ImageMimeType::Webp)
+    } else if starts_with(4, b"ftypavif") || starts_with(4, b"ftypavis") {
+        Some(ImageMimeType::Avif)
+    } else if starts_with(0, b"BM") {
+        Some(ImageMimeType::Bmp)
+    } else if starts_with(0, &[0x00, 0x00, 0x01, 0x00]) {
+        Some(ImageMimeType::Ico)
+    } else {
+        // SVG is text, the root element may follow an XML declaration, comments or a doctype
+        // while HTML pages, e.g. error pages with inline icons, start with their own root
+        let head = String::from_utf8_lossy(&content[..content.len().min(1024)]);
+        let head = head.trim_start_matches('\u{FEFF}').trim_start();
+        let is_prolog = head.starts_with("<?xml")
+            || head.starts_with("<!--")
+            || head.to_lowercase().starts_with("<!doctype svg");
+
+        match head.starts_with("<svg") || (is_prolog && head.contains("<svg")) {
+            true => Some(ImageMimeType::Svg),
+            false => None,
+        }
+    }
+}
+
+// Intrinsic size read from the image header, None when it cannot be read,
+// e.g. for content that is not an image or an SVG image without size
+pub fn natural_dimensions(content: &[u8]) -> Option<(u32, u32)> {
+    match sniff_mime_type(content)? {
+        ImageMimeType::Avif => avif_dimensions(content),
+        ImageMimeType::Svg => svg_dimensions(content),
+        _ => ::image::ImageReader::new(Cursor::new(content))
+            .with_guessed_format()
+            .ok()?
+            .into_dimensions()
+            .ok(),
+    }
+}
+
+// Size of the `ispe` property of the primary image, AVIF cannot be decoded without a native library
+fn avif_dimensions(content: &[u8]) -> Option<(u32, u32)> {
+    let start = content.windows(4).position(|window| window == b"ispe")? + 4;
+    // The property is a full box, its version and flags come before the size
+    let field = |offset: usize| {
+        content
+            .get(start + offset..start + offset + 4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+
+    Some((field(4)?, field(8)?)).filter(|&(width, height)| width > 0 && height > 0)
+}
+
+// Size attributes of an SVG root element
+static SVG_SIZE_ATTRIBUTE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\s(width|height|viewBox)\s*=\s*["']([^"']*)["']"#).unwrap());
+
+// Size of the root element from its width and height in pixels, or from its viewBox
+fn svg_dimensions(content: &[u8]) -> Option<(u32, u32)> {
+    let text = String::from_utf8_lossy(&content[..content.len().min(4096)]);
+    let start = text.find("<svg")?;
+    let root = &text[start..start + text[start..].find('>')?];
+    let attr = |name: &str| {
+        SVG_SIZE_ATTRIBUTE
+            .captures_iter(root)
+            .find(|captures| &captures[1] == name)
+            .map(|captures| captures[2].trim().to_string())
+    };
+    let pixels = |value: String| {
+        value
+            .strip_suffix("px")
+            .unwrap_or(&value)
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|pixels| pixels.is_finite() && *pixels >= 1.0)
+            .map(|pixels| pixels.round() as u32)
+    };
+
+    if let (Some(width), Some(height)) = (
+        attr("width").and_then(pixels),
+        attr("height").and_then(pixels),
+    ) {
+        return Some((width, height));
+    }
+
+    let view_box = attr("viewBox")?;
+    let values = view_box
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>();
+
+    match values.as_slice() {
+        [_, _, width, height] => Some((pixels(width.to_string())?, pixels(height.to_string())?)),
+        _ => None,
+    }
+}
+
+// File extension of a MIME type, types missing from the table fall back to their
+// subtype without `x-` prefix and `+suffix`, e.g. `image/x-portable-pixmap`
+pub fn mime_extension(media_type: &str) -> String {
+    if let Some(extension) = ImageMimeType::from_str(media_type)
+        .ok()
+        .and_then(|mime_type| mime_type.extension())
+    {
+        return String::from(extension);
+    }
+
+    let subtype = media_type
+        .split_once('/')
+        .map(|(_, subtype)| subtype)
+        .unwrap_or_default();
+    let subtype = subtype.split('+').next().unwrap_or_default();
+    let subtype = subtype.strip_prefix("x-").unwrap_or(subtype);
+
+    match subtype.is_empty() {
+        true => String::from("bin"),
+        false => sanitize_component(&subtype.to_lowercase()),
+    }
+}
+
 #[derive(Debug, Getters)]
 pub struct ScrapedImage {
+    page_url: String,
+    src: String,
     title: String,
+    // Position of the img element in the page, starting at 1
+    index: usize,
     mime_type: String,
-    encoded_content: String,
+    content: Vec<u8>,
+    // Only computed when a similarity check needs it
+    perceptual_hash: Option<PerceptualHash>,
 }
 
 impl ScrapedImage {
-    pub fn save(&self, path: &str) -> ScrapeResult<()> {
-        let content = decode(&self.encoded_content).unwrap();
-        let slash_index = self.mime_type.find("/").unwrap_or(0);
-        let extension = self
-            .mime_type
-            .chars()
-            .skip(slash_index + 1)
-            .collect::<String>();
-        let now: DateTime<Utc> = SystemTime::now().into();
-        let name = now.timestamp_millis();
-
-        fs::create_dir_all(format!("{}/{}", path, self.title))
-            .expect("Unable to create desitnation folder");
-
-        if fs::write(
-            format!("{}/{}/{}.{}", path, self.title, name, extension),
-            content,
-        )
-        .is_err()
-        {
-            println!(
-                "Failed to save image: {}/{}/{}.{}",
-                path, self.title, name, extension
-            );
+    pub fn journal_key(&self) -> String {
+        CrawlJournal::asset_key(&self.page_url, &self.src)
+    }
+
+    pub fn into_content(self) -> Vec<u8> {
+        self.content
+    }
+
+    pub fn content_hash(&self) -> String {
+        content_hash(&self.content)
+    }
+
+    // Replaces the content by its converted version, the MIME type follows
+    pub fn convert(&mut self, conversion: &ImageConversion) -> ScrapeResult<()> {
+        if let Some((mime_type, content)) = conversion.apply(&self.content)? {
+            self.mime_type = mime_type;
+            self.content = content;
         }
 
         Ok(())
     }
+
+    // Natural size read from the image header, None when the format is unknown
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        natural_dimensions(&self.content)
+    }
+
+    pub fn extension(&self) -> String {
+        mime_extension(&self.mime_type)
+    }
+
+    // Never overwrites another image, a numeric suffix is added on collisions.
+    // A file with the same content is reused so that reruns keep their names
+    fn file_path(
+        &self,
+        path: &str,
+        template: &ImageNameTemplate,
+        sanitizer: &mut PathSanitizer,
+    ) -> ScrapeResult<PathBuf> {
+        let hash = self.content_hash();
+        let extension = self.extension();
+        let name = template.render(
+            &NameValues {
+                title: &self.title,
+                index: self.index,
+                src: &self.src,
+                page_url: &self.page_url,
+                hash: &hash,
+                ext: &extension,
+            },
+            sanitizer,
+        );
+        let file = Path::new(path).join(name);
+        let dir = file.parent().map(Path::to_path_buf).unwrap_or_default();
+        let stem = file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let suffix_extension = file
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        let mut candidate = file.clone();
+        let mut suffix = 1;
+
+        fs::create_dir_all(&dir).map_err(ScrapeError::IoError)?;
+
+        while candidate.exists() && !fs::read(&candidate).is_ok_and(|c| c == self.content) {
+            candidate = dir.join(format!("{stem}-{suffix}{suffix_extension}"));
+            suffix += 1;
+        }
+
+        Ok(candidate)
+    }
+
+    pub fn save(
+        &self,
+        path: &str,
+        template: &ImageNameTemplate,
+        sanitizer: &mut PathSanitizer,
+    ) -> ScrapeResult<PathBuf> {
+        let file = self.file_path(path, template, sanitizer)?;
+
+        fs::write(&file, &self.content).map_err(ScrapeError::IoError)?;
+
+        Ok(file)
+    }
+
+    // Falls back to a copy when the original is on another file system
+    pub fn link(
+        &self,
+        path: &str,
+        template: &ImageNameTemplate,
+        sanitizer: &mut PathSanitizer,
+        original: &Path,
+    ) -> ScrapeResult<PathBuf> {
+        let file = self.file_path(path, template, sanitizer)?;
+
+        if file.exists() {
+            return Ok(file);
+        }
+
+        match fs::hard_link(original, &file) {
+            Ok(_) => Ok(file),
+            Err(_) => fs::write(&file, &self.content)
+                .map(|_| file)
+                .map_err(ScrapeError::IoError),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum SizeSource {
+    // Size of the downloaded image itself
+    #[default]
+    Natural,
+    // Size of the element on the page, checked before downloading
+    Rendered,
+}
+
+impl Display for SizeSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SizeSource::Natural => write!(f, "natural"),
+            SizeSource::Rendered => write!(f, "rendered"),
+        }
+    }
+}
+
+impl FromStr for SizeSource {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "natural" => Ok(Self::Natural),
+            "rendered" => Ok(Self::Rendered),
+            _ => Err("Unsupported size source"),
+        }
+    }
+}
+
+// Width divided by height, written as a ratio `16:9` or as a number `1.78`
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub struct AspectRatio(f64);
+
+impl Display for AspectRatio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for AspectRatio {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ratio = match s.split_once(':') {
+            Some((width, height)) => {
+                match (width.trim().parse::<f64>(), height.trim().parse::<f64>()) {
+                    (Ok(width), Ok(height)) if height > 0.0 => width / height,
+                    _ => return Err("Invalid aspect ratio, expected e.g. 16:9 or 1.78"),
+                }
+            }
+            None => s
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| "Invalid aspect ratio, expected e.g. 16:9 or 1.78")?,
+        };
+
+        match ratio.is_finite() && ratio > 0.0 {
+            true => Ok(Self(ratio)),
+            false => Err("Aspect ratio must be positive"),
+        }
+    }
+}
+
+impl AspectRatio {
+    pub fn of(width: usize, height: usize) -> Option<Self> {
+        match height {
+            0 => None,
+            _ => Some(Self(width as f64 / height as f64)),
+        }
+    }
+}
+
+// Include and exclude patterns on a text, a missing attribute is an empty text
+#[derive(Clone, Default, Debug)]
+pub struct PatternFilter {
+    include: Option<Regex>,
+    exclude: Option<Regex>,
+}
+
+impl PatternFilter {
+    pub fn set_include(&mut self, regex: Regex) -> &mut Self {
+        self.include = Some(regex);
+
+        self
+    }
+
+    pub fn set_exclude(&mut self, regex: Regex) -> &mut Self {
+        self.exclude = Some(regex);
+
+        self
+    }
+
+    pub fn is_match(&self, value: &str) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(value))
+            && !self
+                .exclude
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(value))
+    }
 }
 
 #[derive(Clone)]
 pub struct ScrapeImageFilter {
     min_width: usize,
     min_height: usize,
+    max_width: Option<usize>,
+    max_height: Option<usize>,
+    min_aspect_ratio: Option<AspectRatio>,
+    max_aspect_ratio: Option<AspectRatio>,
+    min_bytes: Option<usize>,
+    max_bytes: Option<usize>,
     mime_types: Vec<ImageMimeType>,
+    size_source: SizeSource,
+    // Keep images whose type or size cannot be read instead of skipping them
+    allow_unknown_size: bool,
+    src: PatternFilter,
+    alt: PatternFilter,
+    class: PatternFilter,
+    similarity: SimilarityFilter,
 }
 
 impl Default for ScrapeImageFilter {
@@ -89,7 +492,19 @@ impl Default for ScrapeImageFilter {
         Self {
             min_width: 300,
             min_height: 300,
+            max_width: None,
+            max_height: None,
+            min_aspect_ratio: None,
+            max_aspect_ratio: None,
+            min_bytes: None,
+            max_bytes: None,
             mime_types: vec![ImageMimeType::Jpeg],
+            size_source: SizeSource::default(),
+            allow_unknown_size: false,
+            src: PatternFilter::default(),
+            alt: PatternFilter::default(),
+            class: PatternFilter::default(),
+            similarity: SimilarityFilter::default(),
         }
     }
 }
@@ -107,6 +522,94 @@ impl ScrapeImageFilter {
         self
     }
 
+    pub fn set_max_width(&mut self, width: usize) -> &mut Self {
+        self.max_width = Some(width);
+
+        self
+    }
+
+    pub fn set_max_height(&mut self, height: usize) -> &mut Self {
+        self.max_height = Some(height);
+
+        self
+    }
+
+    pub fn set_min_aspect_ratio(&mut self, ratio: AspectRatio) -> &mut Self {
+        self.min_aspect_ratio = Some(ratio);
+
+        self
+    }
+
+    pub fn set_max_aspect_ratio(&mut self, ratio: AspectRatio) -> &mut Self {
+        self.max_aspect_ratio = Some(ratio);
+
+        self
+    }
+
+    pub fn set_min_bytes(&mut self, bytes: usize) -> &mut Self {
+        self.min_bytes = Some(bytes);
+
+        self
+    }
+
+    pub fn set_max_bytes(&mut self, bytes: usize) -> &mut Self {
+        self.max_bytes = Some(bytes);
+
+        self
+    }
+
+    pub fn set_src_filter(&mut self, filter: PatternFilter) -> &mut Self {
+        self.src = filter;
+
+        self
+    }
+
+    pub fn set_alt_filter(&mut self, filter: PatternFilter) -> &mut Self {
+        self.alt = filter;
+
+        self
+    }
+
+    pub fn set_class_filter(&mut self, filter: PatternFilter) -> &mut Self {
+        self.class = filter;
+
+        self
+    }
+
+    pub fn src_filter(&self) -> &PatternFilter {
+        &self.src
+    }
+
+    pub fn alt_filter(&self) -> &PatternFilter {
+        &self.alt
+    }
+
+    pub fn class_filter(&self) -> &PatternFilter {
+        &self.class
+    }
+
+    pub fn set_similarity(&mut self, similarity: SimilarityFilter) -> &mut Self {
+        self.similarity = similarity;
+
+        self
+    }
+
+    pub fn similarity(&self) -> &SimilarityFilter {
+        &self.similarity
+    }
+
+    pub fn set_size_source(&mut self, size_source: SizeSource) -> &mut Self {
+        self.size_source = size_source;
+
+        self
+    }
+
+    pub fn set_allow_unknown_size(&mut self, allow: bool) -> &mut Self {
+        self.allow_unknown_size = allow;
+
+        self
+    }
+
     pub fn replace_mime_types(&mut self, mime_types: Vec<ImageMimeType>) -> &mut Self {
         self.mime_types = mime_types;
 
@@ -122,147 +625,306 @@ impl ScrapeImageFilter {
     }
 
     pub fn remove_mime_type(&mut self, mime_type: ImageMimeType) -> &mut Self {
-        match self.mime_types.iter().position(|&t| t == mime_type) {
-            Some(removed_index) => {
-                self.mime_types.remove(removed_index);
-            }
-            None => {}
-        };
+        if let Some(removed_index) = self.mime_types.iter().position(|&t| t == mime_type) {
+            self.mime_types.remove(removed_index);
+        }
 
         self
     }
 }
 
 pub struct ImageScraper {
-    tx: Sender<ScrapedImage>,
+    tx: Sender<ScrapeMessage<ScrapedImage>>,
     filter: ScrapeImageFilter,
+    context: ScrapeContext,
 }
 
 impl ImageScraper {
-    pub fn new(tx: Sender<ScrapedImage>, filter: ScrapeImageFilter) -> Self {
-        Self { tx, filter }
+    pub fn new(
+        tx: Sender<ScrapeMessage<ScrapedImage>>,
+        filter: ScrapeImageFilter,
+        context: ScrapeContext,
+    ) -> Self {
+        Self {
+            tx,
+            filter,
+            context,
+        }
     }
 
-    async fn is_valid_size(&self, img: &WebElement, width: usize, height: usize) -> bool {
-        // Property value is only returned in the error =))
-        match img.prop("width").await.err() {
-            Some(WebDriverError::CmdError(CmdError::NotW3C(value))) => {
-                if value.as_u64().unwrap_or(0) < width as u64 {
-                    return false;
-                }
+    // Unknown sizes cannot be checked, they are only accepted when allowed
+    fn is_valid_size(&self, width: Option<usize>, height: Option<usize>) -> bool {
+        let filter = &self.filter;
+        let (width, height) = match width.zip(height) {
+            Some(dimensions) => dimensions,
+            None => return filter.allow_unknown_size,
+        };
 
-                match img.prop("height").await.err() {
-                    Some(WebDriverError::CmdError(CmdError::NotW3C(value))) => {
-                        value.as_u64().unwrap_or(0) >= height as u64
-                    }
-                    _ => false,
-                }
-            }
-            _ => false,
-        }
+        width >= filter.min_width
+            && filter.max_width.is_none_or(|max| width <= max)
+            && height >= filter.min_height
+            && filter.max_height.is_none_or(|max| height <= max)
+            && AspectRatio::of(width, height).is_none_or(|ratio| {
+                filter.min_aspect_ratio.is_none_or(|min| ratio >= min)
+                    && filter.max_aspect_ratio.is_none_or(|max| ratio <= max)
+            })
+    }
+
+    fn is_valid_byte_size(&self, size: usize) -> bool {
+        self.filter.min_bytes.is_none_or(|min| size >= min)
+            && self.filter.max_bytes.is_none_or(|max| size <= max)
     }
 
-    fn is_valid_mime_type(&self, accepted_types: &Vec<ImageMimeType>, media_type: &str) -> bool {
-        match ImageMimeType::from_str(media_type) {
-            Ok(mime_type) => accepted_types.contains(&mime_type),
-            Err(_) => false,
+    fn is_valid_element(&self, img: &PageElement, src: &str) -> bool {
+        let attr = |name| img.attr(name).map(String::as_str).unwrap_or_default();
+
+        self.filter.src.is_match(src)
+            && self.filter.alt.is_match(attr("alt"))
+            && self.filter.class.is_match(attr("class"))
+    }
+
+    fn is_valid_mime_type(&self, accepted_types: &[ImageMimeType], media_type: &str) -> bool {
+        accepted_types
+            .iter()
+            .any(|mime_type| mime_type.accepts(media_type))
+    }
+
+    // Transcoding is CPU bound, it runs on the blocking pool so that other workers keep going
+    async fn convert(&self, mut image: ScrapedImage) -> ScrapeResult<ScrapedImage> {
+        let conversion = self.context.strategy().conversion().clone();
+
+        if !conversion.is_enabled() {
+            return Ok(image);
         }
+
+        tokio::task::spawn_blocking(move || {
+            if let Err(err) = image.convert(&conversion) {
+                println!("Keeping {} unconverted: {err}", image.src());
+            }
+
+            image
+        })
+        .await
+        .map_err(|err| ScrapeError::DecodeError(err.to_string()))
     }
 
-    // Format of data url: data:[<mediatype>][;base64],<data>
-    fn get_data(
-        &self,
-        data_url: String,
-        mime_types: &Vec<ImageMimeType>,
-    ) -> Option<(String, String)> {
-        match DataUrl::parse(data_url.as_str()) {
-            Ok(parsed) => {
-                if self.is_valid_mime_type(mime_types, parsed.get_media_type()) {
-                    let comma_index = data_url.find(",").unwrap_or(0);
-                    // Skip comma and all characters before it
-                    let data = data_url.chars().skip(comma_index + 1).collect::<String>();
-
-                    Some((String::from(parsed.get_media_type()), data))
-                } else {
-                    None
+    async fn send(&self, message: ScrapeMessage<ScrapedImage>) -> ScrapeResult<()> {
+        self.tx
+            .send(message)
+            .await
+            .map_err(|_| ScrapeError::ChannelClosed)
+    }
+
+    // Returns false if some images of the page could not be downloaded
+    async fn scrape_page(&self, engine: &mut dyn Engine, url: &String) -> ScrapeResult<bool> {
+        let journal = self.context.journal();
+        let mut is_complete = true;
+
+        self.context.goto(engine, url).await?;
+
+        let title = engine.title().await.unwrap_or(url.to_string());
+        let page_url = match engine.current_url().await {
+            Ok(page_url) => page_url,
+            Err(_) => {
+                Url::parse(url).map_err(|err| ScrapeError::NavigationError(err.to_string()))?
+            }
+        };
+        let img_tags = engine.find_all("img", &["src", "alt", "class"]).await?;
+
+        for (index, img) in img_tags.into_iter().enumerate() {
+            if self.filter.size_source == SizeSource::Rendered
+                && !self.is_valid_size(*img.width(), *img.height())
+            {
+                continue;
+            }
+
+            // Relative sources are resolved so that they are throttled by their own host
+            let src = match img.attr("src").map(|src| page_url.join(src)) {
+                Some(Ok(src)) => src.to_string(),
+                _ => continue,
+            };
+
+            if !self.is_valid_element(&img, &src) {
+                continue;
+            }
+
+            if journal.is_saved(&CrawlJournal::asset_key(url, &src)) {
+                continue;
+            }
+
+            let (mime_type, content) = match self.context.fetch(engine, &src).await {
+                Ok(resource) => resource,
+                Err(err) => {
+                    self.context.report_failure(&src, err);
+                    is_complete = false;
+                    continue;
                 }
+            };
+
+            // The content tells the real type, the header is only trusted when
+            // unknown content is allowed, e.g. an HTML error page is not an image
+            let mime_type = match sniff_mime_type(&content) {
+                Some(sniffed) => sniffed.to_string(),
+                None if self.filter.allow_unknown_size => mime_type,
+                None => continue,
+            };
+
+            if !self.is_valid_mime_type(&self.filter.mime_types, &mime_type)
+                || !self.is_valid_byte_size(content.len())
+            {
+                continue;
             }
-            Err(_) => None,
-        }
-    }
 
-    async fn read_data_url(
-        &self,
-        driver: &WebDriver,
-        src: &str,
-        mime_types: &Vec<ImageMimeType>,
-    ) -> Option<(String, String)> {
-        let result = driver
-            .execute(
-                format!(
-                    "return fetch('{src}')
-                    .then(response => response.blob())
-                    .then(blob => new Promise(callback => {{
-                        let reader = new FileReader();
-                        reader.onload = function() {{
-                            callback(this.result);
-                        }};
-                        reader.readAsDataURL(blob);
-                    }}))
-                    .then(data => data);"
-                )
-                .as_str(),
-                vec![],
-            )
-            .await;
-
-        match result {
-            Ok(result) => match result.convert() {
-                Ok(data_url) => self.get_data(data_url, mime_types),
-                Err(_) => None,
-            },
-            Err(_) => None,
+            if self.filter.size_source == SizeSource::Natural {
+                let dimensions = natural_dimensions(&content);
+
+                if !self.is_valid_size(
+                    dimensions.map(|(width, _)| width as usize),
+                    dimensions.map(|(_, height)| height as usize),
+                ) {
+                    continue;
+                }
+            }
+
+            // Hashing decodes the image, it runs on the blocking pool like conversions
+            let (content, perceptual_hash) = match self.filter.similarity.is_enabled() {
+                true => tokio::task::spawn_blocking(move || {
+                    let hash = PerceptualHash::of(&content);
+
+                    (content, hash)
+                })
+                .await
+                .map_err(|err| ScrapeError::DecodeError(err.to_string()))?,
+                false => (content, None),
+            };
+
+            if let Some(path) = perceptual_hash
+                .as_ref()
+                .and_then(|hash| self.filter.similarity.blacklisted(hash))
+            {
+                println!("Skipping {src}, similar to blacklisted {}", path.display());
+                continue;
+            }
+
+            let image = ScrapedImage {
+                page_url: url.clone(),
+                src,
+                title: title.clone(),
+                index: index + 1,
+                mime_type,
+                content,
+                perceptual_hash,
+            };
+
+            self.send(ScrapeMessage::Data(self.convert(image).await?))
+                .await?;
         }
+
+        Ok(is_complete)
     }
 }
 
 #[async_trait]
 impl Scrape for ImageScraper {
-    async fn scrape(&mut self, urls: &Vec<String>) -> ScrapeResult<()> {
-        let driver = new_driver().await?;
+    async fn scrape(&mut self) -> ScrapeResult<()> {
+        let mut engine = new_engine(&self.context).await?;
+        let queue = self.context.queue().clone();
 
-        for url in urls {
-            driver.goto(url).await.unwrap();
+        while let Some(job) = queue.pop(self.context.limiter()).await {
+            let url = job.url();
 
-            let title = driver.title().await.unwrap_or(url.to_string());
-            let img_tags = driver.find_all(By::Tag("img")).await.unwrap();
-
-            for img in img_tags {
-                if !self
-                    .is_valid_size(&img, self.filter.min_width, self.filter.min_height)
-                    .await
-                {
-                    continue;
-                }
+            if self.context.journal().is_visited(url) {
+                continue;
+            }
 
-                if let Some(src) = img.attr("src").await.unwrap() {
-                    if let Some((mime_type, data)) = self
-                        .read_data_url(&driver, &src, &self.filter.mime_types)
-                        .await
-                    {
-                        self.tx
-                            .send(ScrapedImage {
-                                title: title.clone(),
-                                mime_type,
-                                encoded_content: data,
-                            })
-                            .await
-                            .unwrap();
-                    }
-                }
+            match self.scrape_page(engine.as_mut(), url).await {
+                Ok(true) => self.send(ScrapeMessage::PageDone(url.clone())).await?,
+                // Page stays unvisited so that a resumed run retries its failed images
+                Ok(false) => {}
+                Err(ScrapeError::ChannelClosed) => return Err(ScrapeError::ChannelClosed),
+                Err(err) => self.context.report_failure(url, err),
             }
         }
 
-        Ok(driver.quit().await.unwrap())
+        engine.quit().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniff_reads_magic_bytes() {
+        assert_eq!(
+            sniff_mime_type(b"\xFF\xD8\xFF\xE0"),
+            Some(ImageMimeType::Jpeg)
+        );
+        assert_eq!(
+            sniff_mime_type(b"\x89PNG\r\n\x1A\n...."),
+            Some(ImageMimeType::Png)
+        );
+        assert_eq!(sniff_mime_type(b"GIF89a"), Some(ImageMimeType::Gif));
+        assert_eq!(
+            sniff_mime_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some(ImageMimeType::Webp)
+        );
+        assert_eq!(
+            sniff_mime_type(b"\0\0\0\x1CftypavifXXXX"),
+            Some(ImageMimeType::Avif)
+        );
+        assert_eq!(sniff_mime_type(b"BM...."), Some(ImageMimeType::Bmp));
+        assert_eq!(
+            sniff_mime_type(b"\0\0\x01\0\x01\0"),
+            Some(ImageMimeType::Ico)
+        );
+    }
+
+    #[test]
+    fn sniff_tells_svg_from_html() {
+        assert_eq!(
+            sniff_mime_type(b"<?xml version=\"1.0\"?>\n<!-- icon -->\n<svg/>"),
+            Some(ImageMimeType::Svg)
+        );
+        assert_eq!(
+            sniff_mime_type(b"\xEF\xBB\xBF <svg xmlns=\"http://www.w3.org/2000/svg\"/>"),
+            Some(ImageMimeType::Svg)
+        );
+        assert_eq!(
+            sniff_mime_type(b"<!DOCTYPE html><html><body><svg/></body></html>"),
+            None
+        );
+        assert_eq!(sniff_mime_type(b""), None);
+    }
+
+    #[test]
+    fn natural_dimensions_of_headers_without_decoder() {
+        let mut avif = b"\0\0\0\x18ftypavif\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x14ispe\0\0\0\0".to_vec();
+
+        avif.extend(640u32.to_be_bytes());
+        avif.extend(480u32.to_be_bytes());
+
+        assert_eq!(natural_dimensions(&avif), Some((640, 480)));
+        assert_eq!(
+            natural_dimensions(b"<svg width=\"24px\" height='16'/>"),
+            Some((24, 16))
+        );
+        assert_eq!(
+            natural_dimensions(b"<svg viewBox=\"0 0 1200 800\"/>"),
+            Some((1200, 800))
+        );
+        assert_eq!(natural_dimensions(b"<svg width=\"100%\"/>"), None);
+        assert_eq!(natural_dimensions(b"<html>Not found</html>"), None);
+    }
+
+    #[test]
+    fn aspect_ratio_from_ratio_or_number() {
+        assert_eq!(AspectRatio::from_str("16:9"), Ok(AspectRatio(16.0 / 9.0)));
+        assert_eq!(AspectRatio::from_str(" 1.5 "), Ok(AspectRatio(1.5)));
+        assert_eq!(AspectRatio::of(300, 200), Some(AspectRatio(1.5)));
+        assert_eq!(AspectRatio::of(300, 0), None);
+        assert!(AspectRatio::from_str("16:0").is_err());
+        assert!(AspectRatio::from_str("0").is_err());
+        assert!(AspectRatio::from_str("wide").is_err());
     }
 }
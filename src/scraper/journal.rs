@@ -0,0 +1,196 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use crate::error::{ScrapeError, ScrapeResult};
+
+pub const JOURNAL_FILE: &str = ".scraper-journal";
+
+const VISITED_ENTRY: &str = "visited";
+const SAVED_ENTRY: &str = "saved";
+
+#[derive(Default)]
+struct JournalState {
+    file: Option<File>,
+    visited: HashSet<String>,
+    saved: HashSet<String>,
+    // Saved keys in the order they were recorded
+    saved_order: Vec<String>,
+}
+
+impl JournalState {
+    fn insert_saved(&mut self, key: &str) -> bool {
+        let inserted = self.saved.insert(String::from(key));
+
+        if inserted {
+            self.saved_order.push(String::from(key));
+        }
+
+        inserted
+    }
+}
+
+// Append-only record of completed pages and persisted assets,
+// one `<entry>\t<value>` per line
+#[derive(Default)]
+pub struct CrawlJournal {
+    state: Mutex<JournalState>,
+}
+
+impl CrawlJournal {
+    pub fn open(dir: &str, resume: bool) -> ScrapeResult<Self> {
+        let path = Path::new(dir).join(JOURNAL_FILE);
+        let mut state = JournalState::default();
+
+        fs::create_dir_all(dir).map_err(ScrapeError::IoError)?;
+
+        if resume && path.is_file() {
+            let f = File::open(&path).map_err(ScrapeError::IoError)?;
+
+            for line in BufReader::new(f).lines().map_while(Result::ok) {
+                match line.split_once('\t') {
+                    Some((VISITED_ENTRY, url)) => {
+                        state.visited.insert(String::from(url));
+                    }
+                    Some((SAVED_ENTRY, key)) => {
+                        state.insert_saved(key);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        state.file = Some(
+            fs::OpenOptions::new()
+                .create(true)
+                .append(resume)
+                .write(true)
+                .truncate(!resume)
+                .open(&path)
+                .map_err(ScrapeError::IoError)?,
+        );
+
+        Ok(Self {
+            state: Mutex::new(state),
+        })
+    }
+
+    pub fn asset_key(page_url: &str, asset: &str) -> String {
+        format!("{page_url} {asset}")
+    }
+
+    pub fn is_visited(&self, url: &str) -> bool {
+        self.state.lock().unwrap().visited.contains(url)
+    }
+
+    pub fn is_saved(&self, key: &str) -> bool {
+        self.state.lock().unwrap().saved.contains(key)
+    }
+
+    // Every saved key in recording order, including the ones recorded by a resumed run
+    pub fn saved_keys(&self) -> Vec<String> {
+        self.state.lock().unwrap().saved_order.clone()
+    }
+
+    pub fn mark_visited(&self, url: &str) {
+        self.record(VISITED_ENTRY, url);
+    }
+
+    pub fn mark_saved(&self, key: &str) {
+        self.record(SAVED_ENTRY, key);
+    }
+
+    fn record(&self, entry: &str, value: &str) {
+        let mut state = self.state.lock().unwrap();
+        let inserted = match entry {
+            VISITED_ENTRY => state.visited.insert(String::from(value)),
+            _ => state.insert_saved(value),
+        };
+
+        if !inserted {
+            return;
+        }
+
+        if let Some(f) = state.file.as_mut() {
+            if writeln!(f, "{entry}\t{value}").is_err() {
+                println!("Failed to write journal entry: {entry} {value}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn journal_dir(name: &str) -> String {
+        let dir = env::temp_dir().join(format!("scraper-journal-{name}-{}", std::process::id()));
+
+        let _ = fs::remove_dir_all(&dir);
+
+        dir.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn resume_reads_back_recorded_entries() {
+        let dir = journal_dir("resume");
+        let key = CrawlJournal::asset_key("https://example.com/1", "https://example.com/a.png");
+
+        {
+            let journal = CrawlJournal::open(&dir, false).unwrap();
+
+            journal.mark_visited("https://example.com/1");
+            journal.mark_saved(&key);
+        }
+
+        let journal = CrawlJournal::open(&dir, true).unwrap();
+
+        assert!(journal.is_visited("https://example.com/1"));
+        assert!(journal.is_saved(&key));
+        assert!(!journal.is_visited("https://example.com/2"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fresh_run_discards_previous_entries() {
+        let dir = journal_dir("fresh");
+
+        CrawlJournal::open(&dir, false)
+            .unwrap()
+            .mark_visited("https://example.com/1");
+
+        let journal = CrawlJournal::open(&dir, false).unwrap();
+
+        assert!(!journal.is_visited("https://example.com/1"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saved_keys_keep_recording_order() {
+        let journal = CrawlJournal::default();
+
+        journal.mark_saved("https://example.com/b");
+        journal.mark_saved("https://example.com/a");
+        journal.mark_saved("https://example.com/b");
+        journal.mark_saved("https://example.com/c");
+
+        assert_eq!(
+            journal.saved_keys(),
+            vec![
+                "https://example.com/b",
+                "https://example.com/a",
+                "https://example.com/c"
+            ]
+        );
+        assert!(journal.is_saved("https://example.com/a"));
+        assert!(!journal.is_visited("https://example.com/a"));
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use derive_getters::Getters;
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{sleep_until, Instant},
+};
+use url::Url;
+
+#[derive(Getters, Clone, Default)]
+pub struct RateLimit {
+    requests_per_second: Option<f64>,
+    max_windows_per_host: Option<usize>,
+    min_delay: Duration,
+}
+
+impl RateLimit {
+    pub fn set_requests_per_second(&mut self, requests: f64) -> &mut Self {
+        if requests > 0.0 {
+            self.requests_per_second = Some(requests);
+        }
+
+        self
+    }
+
+    pub fn set_max_windows_per_host(&mut self, windows: usize) -> &mut Self {
+        if windows > 0 {
+            self.max_windows_per_host = Some(windows);
+        }
+
+        self
+    }
+
+    pub fn set_min_delay(&mut self, delay: Duration) -> &mut Self {
+        self.min_delay = delay;
+
+        self
+    }
+}
+
+struct HostState {
+    windows: Option<Arc<Semaphore>>,
+    next_request: Instant,
+    next_navigation: Instant,
+    min_delay: Duration,
+}
+
+// Enforces the rate limit per host across every worker sharing it
+pub struct HostLimiter {
+    rate_limit: RateLimit,
+    hosts: Mutex<HashMap<String, HostState>>,
+}
+
+impl HostLimiter {
+    pub fn new(rate_limit: RateLimit) -> Self {
+        Self {
+            rate_limit,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn host(url: &str) -> String {
+        Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_default()
+    }
+
+    fn with_host<T>(&self, url: &str, f: impl FnOnce(&mut HostState) -> T) -> T {
+        let mut hosts = self.hosts.lock().unwrap();
+        let now = Instant::now();
+        let state = hosts.entry(Self::host(url)).or_insert_with(|| HostState {
+            windows: self
+                .rate_limit
+                .max_windows_per_host
+                .map(|windows| Arc::new(Semaphore::new(windows))),
+            next_request: now,
+            next_navigation: now,
+            min_delay: self.rate_limit.min_delay,
+        });
+
+        f(state)
+    }
+
+    // Raises the delay between navigations of a host, e.g. to honor its Crawl-delay
+    pub fn set_host_delay(&self, url: &str, delay: Duration) {
+        self.with_host(url, |state| state.min_delay = state.min_delay.max(delay));
+    }
+
+    // Takes one of the host's windows until the permit is dropped, None when they are
+    // all busy. Hosts without a window limit always give an empty permit
+    pub fn try_acquire_window(&self, url: &str) -> Option<Option<OwnedSemaphorePermit>> {
+        match self.with_host(url, |state| state.windows.clone()) {
+            Some(windows) => windows.try_acquire_owned().ok().map(Some),
+            None => Some(None),
+        }
+    }
+
+    // Waits for the host's next free request slot, navigations also wait for the min delay
+    pub async fn throttle(&self, url: &str, is_navigation: bool) {
+        let interval = self
+            .rate_limit
+            .requests_per_second
+            .map(|requests| Duration::from_secs_f64(1.0 / requests))
+            .unwrap_or_default();
+        let at = self.with_host(url, |state| {
+            let mut at = Instant::now().max(state.next_request);
+
+            if is_navigation {
+                at = at.max(state.next_navigation);
+                state.next_navigation = at + state.min_delay;
+            }
+
+            state.next_request = at + interval;
+
+            at
+        });
+
+        sleep_until(at).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_are_limited_per_host() {
+        let mut rate_limit = RateLimit::default();
+
+        rate_limit.set_max_windows_per_host(2);
+
+        let limiter = HostLimiter::new(rate_limit);
+        let first = limiter.try_acquire_window("https://a.com/1").unwrap();
+        let _second = limiter.try_acquire_window("https://a.com/2").unwrap();
+
+        assert!(first.is_some());
+        assert!(limiter.try_acquire_window("https://a.com/3").is_none());
+        assert!(limiter.try_acquire_window("https://b.com/1").is_some());
+
+        drop(first);
+
+        assert!(limiter.try_acquire_window("https://a.com/3").is_some());
+    }
+
+    #[test]
+    fn hosts_without_window_limit_give_empty_permits() {
+        let limiter = HostLimiter::new(RateLimit::default());
+
+        for _ in 0..10 {
+            assert!(matches!(
+                limiter.try_acquire_window("https://a.com/"),
+                Some(None)
+            ));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn requests_are_spaced_by_the_rate() {
+        let mut rate_limit = RateLimit::default();
+
+        rate_limit.set_requests_per_second(4.0);
+
+        let limiter = HostLimiter::new(rate_limit);
+        let start = Instant::now();
+
+        for expected in [0, 250, 500, 750] {
+            limiter.throttle("https://a.com/image.png", false).await;
+
+            assert_eq!(start.elapsed(), Duration::from_millis(expected));
+        }
+
+        // Another host has its own slots
+        limiter.throttle("https://b.com/", false).await;
+
+        assert_eq!(start.elapsed(), Duration::from_millis(750));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn navigations_also_wait_for_the_host_delay() {
+        let mut rate_limit = RateLimit::default();
+
+        rate_limit
+            .set_requests_per_second(10.0)
+            .set_min_delay(Duration::from_secs(1));
+
+        let limiter = HostLimiter::new(rate_limit);
+        let start = Instant::now();
+
+        limiter.throttle("https://a.com/1", true).await;
+        limiter.throttle("https://a.com/image.png", false).await;
+
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+
+        limiter.throttle("https://a.com/2", true).await;
+
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        // A raised delay spaces the navigations scheduled from now on
+        limiter.set_host_delay("https://a.com/", Duration::from_secs(3));
+        limiter.throttle("https://a.com/3", true).await;
+        limiter.throttle("https://a.com/4", true).await;
+
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+
+use crate::error::{ScrapeError, ScrapeResult};
+
+use super::ScrapedImage;
+
+pub const MANIFEST_FILE: &str = "manifest.jsonl";
+
+// Where a saved image came from, one JSON object per line of the manifest
+#[derive(Debug, Serialize)]
+pub struct ManifestEntry {
+    src: String,
+    page_url: String,
+    title: String,
+    mime_type: String,
+    // None when the content cannot be decoded
+    width: Option<u32>,
+    height: Option<u32>,
+    size: usize,
+    hash: String,
+    // Image file, or the archive holding it when only archives are written
+    path: String,
+    // Skipped or linked because a saved image has the same content
+    duplicate: bool,
+    timestamp: String,
+}
+
+impl ManifestEntry {
+    pub fn new(image: &ScrapedImage, path: &Path, duplicate: bool) -> Self {
+        let now: DateTime<Utc> = SystemTime::now().into();
+        let dimensions = image.dimensions();
+
+        Self {
+            src: image.src().clone(),
+            page_url: image.page_url().clone(),
+            title: image.title().clone(),
+            mime_type: image.mime_type().clone(),
+            width: dimensions.map(|(width, _)| width),
+            height: dimensions.map(|(_, height)| height),
+            size: image.content().len(),
+            hash: image.content_hash(),
+            path: path.to_string_lossy().to_string(),
+            duplicate,
+            timestamp: now.to_rfc3339_opts(SecondsFormat::Millis, true),
+        }
+    }
+}
+
+// Appended by every run so that files saved earlier keep their record
+pub struct ImageManifest {
+    file: File,
+}
+
+impl ImageManifest {
+    pub fn open(dir: &str) -> ScrapeResult<Self> {
+        fs::create_dir_all(dir).map_err(ScrapeError::IoError)?;
+
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Path::new(dir).join(MANIFEST_FILE))
+            .map_err(ScrapeError::IoError)?;
+
+        Ok(Self { file })
+    }
+
+    pub fn write(&mut self, entry: &ManifestEntry) -> ScrapeResult<()> {
+        let line = serde_json::to_string(entry).map_err(|err| ScrapeError::IoError(err.into()))?;
+
+        writeln!(self.file, "{line}").map_err(ScrapeError::IoError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use serde_json::Value;
+
+    use super::*;
+
+    fn entry(src: &str, duplicate: bool) -> ManifestEntry {
+        ManifestEntry {
+            src: String::from(src),
+            page_url: String::from("https://example.com/1"),
+            title: String::from("Chapter 1"),
+            mime_type: String::from("image/png"),
+            width: Some(40),
+            height: None,
+            size: 120,
+            hash: String::from("0123456789abcdef"),
+            path: String::from("images/Chapter 1/001.png"),
+            duplicate,
+            timestamp: String::from("2024-01-01T00:00:00.000Z"),
+        }
+    }
+
+    #[test]
+    fn every_run_appends_one_line_per_image() {
+        let dir = env::temp_dir().join(format!("scraper-manifest-{}", std::process::id()));
+        let dir_name = dir.to_string_lossy().into_owned();
+
+        let _ = fs::remove_dir_all(&dir);
+
+        ImageManifest::open(&dir_name)
+            .unwrap()
+            .write(&entry("https://example.com/a.png", false))
+            .unwrap();
+
+        let mut manifest = ImageManifest::open(&dir_name).unwrap();
+
+        manifest
+            .write(&entry("https://example.com/b.png", true))
+            .unwrap();
+
+        let content = fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+        let lines = content
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["src"], "https://example.com/a.png");
+        assert_eq!(lines[0]["duplicate"], false);
+        assert_eq!(lines[0]["width"], 40);
+        assert_eq!(lines[0]["height"], Value::Null);
+        assert_eq!(lines[1]["src"], "https://example.com/b.png");
+        assert_eq!(lines[1]["duplicate"], true);
+        assert_eq!(lines[1]["path"], "images/Chapter 1/001.png");
+    }
+}
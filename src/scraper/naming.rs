@@ -0,0 +1,323 @@
+use std::{fmt::Display, str::FromStr, time::SystemTime};
+
+use chrono::{DateTime, Utc};
+use url::Url;
+
+use super::{sanitize_component, sanitize_path, PathSanitizer};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Placeholder {
+    Title,
+    UrlPath,
+    Index,
+    UrlBasename,
+    Hash,
+    Host,
+    Ext,
+    Timestamp,
+}
+
+impl FromStr for Placeholder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "title" => Ok(Self::Title),
+            "url_path" => Ok(Self::UrlPath),
+            "index" => Ok(Self::Index),
+            "url_basename" => Ok(Self::UrlBasename),
+            "hash" => Ok(Self::Hash),
+            "host" => Ok(Self::Host),
+            "ext" => Ok(Self::Ext),
+            "timestamp" => Ok(Self::Timestamp),
+            _ => Err(format!("Unknown placeholder {{{s}}}")),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Text(String),
+    // Numbers are padded with zeros and texts truncated to the width
+    Value(Placeholder, Option<usize>),
+}
+
+// Values available to a template for one image
+pub struct NameValues<'a> {
+    pub title: &'a str,
+    pub index: usize,
+    pub src: &'a str,
+    pub page_url: &'a str,
+    pub hash: &'a str,
+    pub ext: &'a str,
+}
+
+// Relative path of a saved image, e.g. `{title}/{index:03}.{ext}`
+#[derive(Clone, Debug)]
+pub struct ImageNameTemplate {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl Default for ImageNameTemplate {
+    fn default() -> Self {
+        Self::from_str("{title}/{timestamp}.{ext}").unwrap()
+    }
+}
+
+impl Display for ImageNameTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl FromStr for ImageNameTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = vec![];
+        let mut rest = s;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Text(String::from(&rest[..start])));
+            }
+
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| format!("Unclosed placeholder in {s}"))?;
+            let (name, width) = match rest[start + 1..end].split_once(':') {
+                Some((name, width)) => (name, Some(width)),
+                None => (&rest[start + 1..end], None),
+            };
+            let placeholder = Placeholder::from_str(name)?;
+            let width = match width {
+                Some(width) => Some(
+                    width
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid width in {{{name}:{width}}}"))?,
+                ),
+                None => None,
+            };
+
+            segments.push(Segment::Value(placeholder, width));
+            rest = &rest[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            segments.push(Segment::Text(String::from(rest)));
+        }
+
+        if segments.is_empty() {
+            return Err(String::from("Image name template is empty"));
+        }
+
+        Ok(Self {
+            source: String::from(s),
+            segments,
+        })
+    }
+}
+
+impl ImageNameTemplate {
+    // Scraped values are truncated to the width first, then sanitized so that they
+    // cannot escape or break the path. `dir` is the folder the value is rendered in
+    fn value(
+        placeholder: Placeholder,
+        width: Option<usize>,
+        values: &NameValues,
+        dir: &str,
+        sanitizer: &mut PathSanitizer,
+    ) -> String {
+        let truncate = |value: &str| match width {
+            Some(width) => value.chars().take(width).collect::<String>(),
+            None => String::from(value),
+        };
+        // Same kind of names in the same folder only are told apart
+        let scope = format!("{placeholder:?} in {dir}");
+
+        match placeholder {
+            Placeholder::Title => sanitizer.claim(
+                &scope,
+                values.title,
+                sanitize_component(&truncate(values.title)),
+            ),
+            Placeholder::UrlPath => {
+                let path = Url::parse(values.page_url)
+                    .map(|url| url.path().to_string())
+                    .unwrap_or_default();
+
+                sanitizer.claim(&scope, &path, sanitize_path(&truncate(&path)))
+            }
+            Placeholder::Index => format!("{:0>width$}", values.index, width = width.unwrap_or(0)),
+            Placeholder::UrlBasename => {
+                let path = Url::parse(values.src)
+                    .or_else(|_| Url::parse(values.page_url).and_then(|url| url.join(values.src)))
+                    .map(|url| url.path().to_string())
+                    .unwrap_or_default();
+                let name = path.rsplit('/').next().unwrap_or_default();
+                let stem = match name.rsplit_once('.') {
+                    Some((stem, _)) if !stem.is_empty() => stem,
+                    _ => name,
+                };
+
+                match stem.is_empty() {
+                    true => String::from("image"),
+                    false => sanitizer.claim(&scope, stem, sanitize_component(&truncate(stem))),
+                }
+            }
+            Placeholder::Hash => truncate(values.hash),
+            Placeholder::Host => {
+                let host = Url::parse(values.page_url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(String::from))
+                    .unwrap_or_default();
+
+                sanitizer.claim(&scope, &host, sanitize_component(&truncate(&host)))
+            }
+            Placeholder::Ext => sanitize_component(&truncate(values.ext)),
+            Placeholder::Timestamp => {
+                let now: DateTime<Utc> = SystemTime::now().into();
+
+                format!(
+                    "{:0>width$}",
+                    now.timestamp_millis(),
+                    width = width.unwrap_or(0)
+                )
+            }
+        }
+    }
+
+    pub fn render(&self, values: &NameValues, sanitizer: &mut PathSanitizer) -> String {
+        let mut name = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => name.push_str(text),
+                Segment::Value(placeholder, width) => {
+                    let dir = name
+                        .rsplit_once('/')
+                        .map(|(dir, _)| dir)
+                        .unwrap_or_default();
+                    let value = Self::value(*placeholder, *width, values, dir, sanitizer);
+
+                    name.push_str(&value);
+                }
+            }
+        }
+
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values<'a>(title: &'a str, src: &'a str) -> NameValues<'a> {
+        NameValues {
+            title,
+            index: 7,
+            src,
+            page_url: "https://example.com/manga/one-piece/1",
+            hash: "0123456789abcdef",
+            ext: "png",
+        }
+    }
+
+    fn render(template: &str, values: &NameValues, sanitizer: &mut PathSanitizer) -> String {
+        ImageNameTemplate::from_str(template)
+            .unwrap()
+            .render(values, sanitizer)
+    }
+
+    #[test]
+    fn parse_rejects_broken_templates() {
+        assert!(ImageNameTemplate::from_str("").is_err());
+        assert!(ImageNameTemplate::from_str("{title").is_err());
+        assert!(ImageNameTemplate::from_str("{name}").is_err());
+        assert!(ImageNameTemplate::from_str("{index:x}").is_err());
+    }
+
+    #[test]
+    fn render_fills_and_pads_placeholders() {
+        let mut sanitizer = PathSanitizer::default();
+        let values = values("Chapter 1", "https://cdn.example.com/img/page.jpg?v=2");
+
+        assert_eq!(
+            render(
+                "{host}/{url_path}/{index:03}-{url_basename}-{hash:8}.{ext}",
+                &values,
+                &mut sanitizer
+            ),
+            "example.com/manga/one-piece/1/007-page-01234567.png"
+        );
+    }
+
+    #[test]
+    fn render_sanitizes_scraped_values() {
+        let mut sanitizer = PathSanitizer::default();
+
+        assert_eq!(
+            render(
+                "{title}/{index}.{ext}",
+                &values("One/Piece", "a.png"),
+                &mut sanitizer
+            ),
+            "One_Piece/7.png"
+        );
+        assert_eq!(
+            render(
+                "{title}/{index}.{ext}",
+                &values("CON", "a.png"),
+                &mut sanitizer
+            ),
+            "_CON/7.png"
+        );
+    }
+
+    #[test]
+    fn render_truncates_before_sanitizing() {
+        let mut sanitizer = PathSanitizer::default();
+
+        // The trailing space left by the truncation is dropped and the suffix is kept
+        assert_eq!(
+            render(
+                "{title:8}/{index}",
+                &values("Chapter 1", "a.png"),
+                &mut sanitizer
+            ),
+            "Chapter/7"
+        );
+        assert_eq!(
+            render(
+                "{title:8}/{index}",
+                &values("Chapter 2", "a.png"),
+                &mut sanitizer
+            ),
+            "Chapter (2)/7"
+        );
+        assert_eq!(
+            render("{title:3}", &values("CON.txt", "a.png"), &mut sanitizer),
+            "_CON"
+        );
+    }
+
+    #[test]
+    fn render_claims_names_per_folder() {
+        let mut sanitizer = PathSanitizer::default();
+        let mut first = |title, src| {
+            render(
+                "{title}/{url_basename}.{ext}",
+                &values(title, src),
+                &mut sanitizer,
+            )
+        };
+
+        assert_eq!(first("A", "x|y.png"), "A/x_y.png");
+        // Same name in another folder does not collide
+        assert_eq!(first("B", "x*y.png"), "B/x_y.png");
+        assert_eq!(first("A", "x*y.png"), "A/x_y (2).png");
+    }
+}
@@ -0,0 +1,74 @@
+use std::{fmt::Display, str::FromStr};
+
+use derive_getters::Getters;
+
+use super::{ScrapeImageFilter, ScrapeStrategy, ScrapeUrlFilter};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StageKind {
+    Urls,
+    Images,
+}
+
+impl Display for StageKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StageKind::Urls => write!(f, "urls"),
+            StageKind::Images => write!(f, "images"),
+        }
+    }
+}
+
+impl FromStr for StageKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "urls" => Ok(Self::Urls),
+            "images" => Ok(Self::Images),
+            _ => Err("Unsupported stage kind"),
+        }
+    }
+}
+
+// One step of a pipeline, the urls scraped by a `Urls` stage are the input of the next stage
+#[derive(Getters, Clone)]
+pub struct Stage {
+    name: String,
+    kind: StageKind,
+    strategy: ScrapeStrategy,
+    url_filter: ScrapeUrlFilter,
+    image_filter: ScrapeImageFilter,
+}
+
+impl Stage {
+    pub fn new(kind: StageKind, strategy: ScrapeStrategy) -> Self {
+        Self {
+            name: kind.to_string(),
+            kind,
+            strategy,
+            url_filter: ScrapeUrlFilter::default(),
+            image_filter: ScrapeImageFilter::default(),
+        }
+    }
+
+    pub fn set_name(&mut self, name: String) -> &mut Self {
+        if !name.is_empty() {
+            self.name = name;
+        }
+
+        self
+    }
+
+    pub fn set_url_filter(&mut self, filter: ScrapeUrlFilter) -> &mut Self {
+        self.url_filter = filter;
+
+        self
+    }
+
+    pub fn set_image_filter(&mut self, filter: ScrapeImageFilter) -> &mut Self {
+        self.image_filter = filter;
+
+        self
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::{Notify, OwnedSemaphorePermit};
+
+use super::HostLimiter;
+
+#[derive(Default)]
+struct QueueState {
+    pending: VecDeque<(String, usize)>,
+    seen: HashSet<String>,
+    in_progress: usize,
+}
+
+// Work queue shared by all workers, URLs discovered at runtime can be pushed
+// while other workers are still pulling from it
+#[derive(Clone, Default)]
+pub struct WorkQueue {
+    state: Arc<Mutex<QueueState>>,
+    notify: Arc<Notify>,
+}
+
+impl WorkQueue {
+    // Returns false if the URL has already been queued once
+    pub fn push(&self, url: String, depth: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        if !state.seen.insert(url.clone()) {
+            return false;
+        }
+
+        state.pending.push_back((url, depth));
+        drop(state);
+        self.notify.notify_waiters();
+
+        true
+    }
+
+    // Waits for the next job whose host has a free window, jobs of busy hosts stay
+    // queued for other workers. Returns None once the queue is drained and no job
+    // in progress can push new URLs anymore
+    pub async fn pop(&self, limiter: &HostLimiter) -> Option<ScrapeJob> {
+        loop {
+            let notified = self.notify.notified();
+
+            {
+                let mut state = self.state.lock().unwrap();
+                let ready = state
+                    .pending
+                    .iter()
+                    .enumerate()
+                    .find_map(|(index, (url, _))| {
+                        limiter
+                            .try_acquire_window(url)
+                            .map(|window| (index, window))
+                    });
+
+                if let Some((index, window)) = ready {
+                    let (url, depth) = state.pending.remove(index).unwrap();
+
+                    state.in_progress += 1;
+
+                    return Some(ScrapeJob {
+                        url,
+                        depth,
+                        window,
+                        queue: self.clone(),
+                    });
+                }
+
+                if state.pending.is_empty() && state.in_progress == 0 {
+                    return None;
+                }
+            }
+
+            notified.await;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn finish(&self) {
+        self.state.lock().unwrap().in_progress -= 1;
+        self.notify.notify_waiters();
+    }
+}
+
+// Job is marked as finished when dropped, even if the worker panics
+pub struct ScrapeJob {
+    url: String,
+    depth: usize,
+    // Held until the job is done to cap the windows opened on its host
+    window: Option<OwnedSemaphorePermit>,
+    queue: WorkQueue,
+}
+
+impl ScrapeJob {
+    pub fn url(&self) -> &String {
+        &self.url
+    }
+
+    pub fn depth(&self) -> &usize {
+        &self.depth
+    }
+}
+
+impl Drop for ScrapeJob {
+    fn drop(&mut self) {
+        // Released first so that woken workers can take the host's window
+        self.window.take();
+        self.queue.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::scraper::RateLimit;
+
+    #[tokio::test]
+    async fn pop_skips_jobs_of_busy_hosts() {
+        let mut rate_limit = RateLimit::default();
+
+        rate_limit.set_max_windows_per_host(1);
+
+        let limiter = HostLimiter::new(rate_limit);
+        let queue = WorkQueue::default();
+
+        queue.push(String::from("https://a.com/1"), 0);
+        queue.push(String::from("https://a.com/2"), 0);
+        queue.push(String::from("https://b.com/1"), 0);
+
+        let first = queue.pop(&limiter).await.unwrap();
+        let second = queue.pop(&limiter).await.unwrap();
+
+        assert_eq!(first.url(), "https://a.com/1");
+        assert_eq!(second.url(), "https://b.com/1");
+
+        // a.com is busy until its job is done
+        assert!(timeout(Duration::from_millis(50), queue.pop(&limiter))
+            .await
+            .is_err());
+
+        drop(first);
+
+        let third = queue.pop(&limiter).await.unwrap();
+
+        assert_eq!(third.url(), "https://a.com/2");
+    }
+
+    #[tokio::test]
+    async fn pop_ends_once_drained() {
+        let limiter = HostLimiter::new(RateLimit::default());
+        let queue = WorkQueue::default();
+
+        assert!(queue.push(String::from("https://a.com/1"), 0));
+        assert!(!queue.push(String::from("https://a.com/1"), 1));
+
+        let job = queue.pop(&limiter).await.unwrap();
+
+        drop(job);
+
+        assert!(queue.pop(&limiter).await.is_none());
+    }
+}
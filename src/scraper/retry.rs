@@ -0,0 +1,209 @@
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use derive_getters::Getters;
+use rand::Rng;
+use tokio::time::sleep;
+
+use crate::error::{ScrapeError, ScrapeResult};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RetryOn {
+    Navigation,
+    Fetch,
+    Timeout,
+    // 5xx responses and 429 Too Many Requests
+    ServerError,
+    Challenge,
+}
+
+impl Display for RetryOn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RetryOn::Navigation => write!(f, "navigation"),
+            RetryOn::Fetch => write!(f, "fetch"),
+            RetryOn::Timeout => write!(f, "timeout"),
+            RetryOn::ServerError => write!(f, "server-error"),
+            RetryOn::Challenge => write!(f, "challenge"),
+        }
+    }
+}
+
+impl FromStr for RetryOn {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "navigation" => Ok(Self::Navigation),
+            "fetch" => Ok(Self::Fetch),
+            "timeout" => Ok(Self::Timeout),
+            "server-error" => Ok(Self::ServerError),
+            "challenge" => Ok(Self::Challenge),
+            _ => Err("Unsupported retryable error"),
+        }
+    }
+}
+
+impl RetryOn {
+    fn matches(&self, err: &ScrapeError) -> bool {
+        match (self, err) {
+            (RetryOn::Navigation, ScrapeError::NavigationError(_)) => true,
+            (RetryOn::Fetch, ScrapeError::FetchError(_)) => true,
+            (RetryOn::Timeout, ScrapeError::TimeoutError(_)) => true,
+            (RetryOn::ServerError, ScrapeError::StatusError(status, _)) => {
+                *status >= 500 || *status == 429
+            }
+            (RetryOn::Challenge, ScrapeError::ChallengeError(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Getters, Clone)]
+pub struct RetryPolicy {
+    max_attempts: usize,
+    base_delay: Duration,
+    jitter: Duration,
+    retry_on: Vec<RetryOn>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            jitter: Duration::from_millis(500),
+            retry_on: vec![RetryOn::Timeout, RetryOn::ServerError, RetryOn::Challenge],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn set_max_attempts(&mut self, attempts: usize) -> &mut Self {
+        if attempts > 0 {
+            self.max_attempts = attempts;
+        }
+
+        self
+    }
+
+    pub fn set_base_delay(&mut self, delay: Duration) -> &mut Self {
+        self.base_delay = delay;
+
+        self
+    }
+
+    pub fn set_jitter(&mut self, jitter: Duration) -> &mut Self {
+        self.jitter = jitter;
+
+        self
+    }
+
+    pub fn replace_retry_on(&mut self, retry_on: Vec<RetryOn>) -> &mut Self {
+        self.retry_on = retry_on;
+
+        self
+    }
+
+    pub fn is_retryable(&self, err: &ScrapeError) -> bool {
+        self.retry_on.iter().any(|retry_on| retry_on.matches(err))
+    }
+
+    // Doubles the base delay after every attempt, plus a random jitter
+    pub fn delay(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16) as u32;
+        let jitter = match self.jitter.is_zero() {
+            true => Duration::ZERO,
+            false => rand::thread_rng().gen_range(Duration::ZERO..=self.jitter),
+        };
+
+        self.base_delay.saturating_mul(2u32.pow(exponent)) + jitter
+    }
+
+    // Gives the error back once it cannot be retried anymore
+    pub async fn wait(&self, url: &str, err: ScrapeError, attempt: usize) -> ScrapeResult<()> {
+        if attempt >= self.max_attempts || !self.is_retryable(&err) {
+            return Err(err);
+        }
+
+        let delay = self.delay(attempt);
+
+        println!("Retrying {url} in {delay:?} ({err})");
+        sleep(delay).await;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(status: u16) -> ScrapeError {
+        ScrapeError::StatusError(status, String::from("https://example.com"))
+    }
+
+    #[test]
+    fn delay_doubles_after_every_attempt() {
+        let mut policy = RetryPolicy::default();
+
+        policy
+            .set_base_delay(Duration::from_millis(100))
+            .set_jitter(Duration::ZERO);
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(4), Duration::from_millis(800));
+        assert_eq!(policy.delay(100), Duration::from_millis(100 << 16));
+    }
+
+    #[test]
+    fn delay_adds_at_most_the_jitter() {
+        let mut policy = RetryPolicy::default();
+
+        policy
+            .set_base_delay(Duration::from_millis(100))
+            .set_jitter(Duration::from_millis(50));
+
+        for _ in 0..20 {
+            let delay = policy.delay(2);
+
+            assert!(delay >= Duration::from_millis(200));
+            assert!(delay <= Duration::from_millis(250));
+        }
+    }
+
+    #[test]
+    fn server_errors_and_rate_limits_are_retried() {
+        let policy = RetryPolicy::default();
+
+        assert!(policy.is_retryable(&status(500)));
+        assert!(policy.is_retryable(&status(503)));
+        assert!(policy.is_retryable(&status(429)));
+        assert!(!policy.is_retryable(&status(404)));
+        assert!(!policy.is_retryable(&status(403)));
+        assert!(!policy.is_retryable(&ScrapeError::FetchError(String::from("reset"))));
+    }
+
+    #[tokio::test]
+    async fn wait_gives_the_error_back_after_max_attempts() {
+        let mut policy = RetryPolicy::default();
+
+        policy
+            .set_max_attempts(2)
+            .set_base_delay(Duration::ZERO)
+            .set_jitter(Duration::ZERO);
+
+        assert!(policy
+            .wait("https://example.com", status(503), 1)
+            .await
+            .is_ok());
+        assert!(matches!(
+            policy.wait("https://example.com", status(503), 2).await,
+            Err(ScrapeError::StatusError(503, _))
+        ));
+        assert!(matches!(
+            policy.wait("https://example.com", status(404), 1).await,
+            Err(ScrapeError::StatusError(404, _))
+        ));
+    }
+}
@@ -0,0 +1,303 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use regex::Regex;
+use reqwest::Client;
+use tokio::sync::OnceCell;
+use url::Url;
+
+use crate::error::{ScrapeError, ScrapeResult};
+
+use super::HostLimiter;
+
+struct RobotsRule {
+    allow: bool,
+    // Length of the original path pattern, the longest matching rule wins
+    specificity: usize,
+    pattern: Regex,
+}
+
+#[derive(Default)]
+struct RobotsGroup {
+    agents: Vec<String>,
+    rules: Vec<RobotsRule>,
+    crawl_delay: Option<Duration>,
+}
+
+#[derive(Default)]
+pub struct RobotsRules {
+    rules: Vec<RobotsRule>,
+    crawl_delay: Option<Duration>,
+}
+
+impl RobotsRules {
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    pub fn disallow_all() -> Self {
+        Self {
+            rules: vec![RobotsRule {
+                allow: false,
+                specificity: 1,
+                pattern: Regex::new("^/").unwrap(),
+            }],
+            crawl_delay: None,
+        }
+    }
+
+    // Keeps the groups addressed to the user agent token, or the `*` groups if none is
+    pub fn parse(content: &str, user_agent: &str) -> Self {
+        let mut groups: Vec<RobotsGroup> = vec![];
+        let mut is_reading_agents = false;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+                None => continue,
+            };
+
+            if key == "user-agent" {
+                if !is_reading_agents {
+                    groups.push(RobotsGroup::default());
+                    is_reading_agents = true;
+                }
+
+                if let Some(group) = groups.last_mut() {
+                    group.agents.push(value.to_lowercase());
+                }
+
+                continue;
+            }
+
+            is_reading_agents = false;
+
+            // Rules before any user-agent line do not belong to a group
+            let group = match groups.last_mut() {
+                Some(group) => group,
+                None => continue,
+            };
+
+            match key.as_str() {
+                "allow" | "disallow" if !value.is_empty() => {
+                    if let Some(pattern) = Self::compile(value) {
+                        group.rules.push(RobotsRule {
+                            allow: key == "allow",
+                            specificity: value.len(),
+                            pattern,
+                        });
+                    }
+                }
+                "crawl-delay" => {
+                    group.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|delay| delay.is_finite() && *delay >= 0.0)
+                        .map(Duration::from_secs_f64);
+                }
+                _ => {}
+            }
+        }
+
+        let token = product_token(user_agent);
+        let is_specific = |group: &RobotsGroup| {
+            group
+                .agents
+                .iter()
+                .any(|agent| agent != "*" && is_agent_match(&product_token(agent), &token))
+        };
+        let matched = match groups.iter().any(is_specific) {
+            true => groups.into_iter().filter(is_specific).collect::<Vec<_>>(),
+            false => groups
+                .into_iter()
+                .filter(|group| group.agents.iter().any(|agent| agent == "*"))
+                .collect(),
+        };
+        let mut rules = Self::default();
+
+        for group in matched {
+            rules.rules.extend(group.rules);
+            rules.crawl_delay = rules.crawl_delay.max(group.crawl_delay);
+        }
+
+        rules
+    }
+
+    // `*` matches any sequence of characters and a trailing `$` anchors the end of the path
+    fn compile(pattern: &str) -> Option<Regex> {
+        let (pattern, is_anchored) = match pattern.strip_suffix('$') {
+            Some(pattern) => (pattern, true),
+            None => (pattern, false),
+        };
+        let body = pattern
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join(".*");
+
+        Regex::new(&format!("^{}{}", body, if is_anchored { "$" } else { "" })).ok()
+    }
+
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+
+        // On equally specific rules, allow wins
+        self.rules
+            .iter()
+            .filter(|rule| rule.pattern.is_match(&path))
+            .max_by_key(|rule| (rule.specificity, rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+// Name of the crawler without its version or comments, e.g. `scraper` for `Scraper/1.0 (+url)`
+fn product_token(user_agent: &str) -> String {
+    user_agent
+        .split(|c: char| c == '/' || c.is_whitespace())
+        .find(|token| !token.is_empty())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+// A group for `scraper` also applies to `scraper-images`, but not to `webscraper`
+fn is_agent_match(agent: &str, token: &str) -> bool {
+    match token.strip_prefix(agent) {
+        Some(rest) => rest.is_empty() || rest.starts_with(['-', '_']),
+        None => false,
+    }
+}
+
+// robots.txt files fetched once per origin and shared by every worker
+pub struct RobotsCache {
+    client: Client,
+    user_agent: String,
+    origins: Mutex<HashMap<String, Arc<OnceCell<Arc<RobotsRules>>>>>,
+}
+
+impl RobotsCache {
+    pub fn new(user_agent: &str) -> ScrapeResult<Self> {
+        let client = Client::builder()
+            .user_agent(user_agent)
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(ScrapeError::HttpError)?;
+
+        Ok(Self {
+            client,
+            user_agent: String::from(user_agent),
+            origins: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub async fn rules(&self, url: &Url, limiter: &HostLimiter) -> Arc<RobotsRules> {
+        let origin = url.origin().ascii_serialization();
+        let cell = self
+            .origins
+            .lock()
+            .unwrap()
+            .entry(origin.clone())
+            .or_default()
+            .clone();
+
+        cell.get_or_init(|| async { Arc::new(self.fetch(&origin, limiter).await) })
+            .await
+            .clone()
+    }
+
+    // Throttled like any other request sent to the host
+    async fn fetch(&self, origin: &str, limiter: &HostLimiter) -> RobotsRules {
+        let robots_url = format!("{origin}/robots.txt");
+
+        limiter.throttle(&robots_url, false).await;
+
+        let response = match self.client.get(&robots_url).send().await {
+            Ok(response) => response,
+            // Unreachable site, its pages will fail on their own
+            Err(_) => return RobotsRules::allow_all(),
+        };
+        let status = response.status();
+
+        if status.is_success() {
+            match response.text().await {
+                Ok(content) => RobotsRules::parse(&content, &self.user_agent),
+                Err(_) => RobotsRules::allow_all(),
+            }
+        } else if status.is_server_error() {
+            // Server errors mean the whole site must be treated as disallowed
+            RobotsRules::disallow_all()
+        } else {
+            RobotsRules::allow_all()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(path: &str) -> Url {
+        Url::parse(&format!("https://example.com{path}")).unwrap()
+    }
+
+    #[test]
+    fn parse_keeps_groups_of_the_product_token() {
+        let content = "User-agent: *\nDisallow: /\n\nUser-agent: Scraper\nDisallow: /private\nCrawl-delay: 2\n";
+        let rules = RobotsRules::parse(content, "scraper/1.0 (+https://example.com/bot)");
+
+        assert!(rules.is_allowed(&url("/public")));
+        assert!(!rules.is_allowed(&url("/private/page")));
+        assert_eq!(rules.crawl_delay(), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn parse_falls_back_to_the_wildcard_group() {
+        let content = "User-agent: otherbot\nDisallow: /\n\nUser-agent: *\nDisallow: /admin\n";
+        let rules = RobotsRules::parse(content, "scraper");
+
+        assert!(rules.is_allowed(&url("/")));
+        assert!(!rules.is_allowed(&url("/admin")));
+    }
+
+    #[test]
+    fn agents_match_on_token_boundaries_only() {
+        let content = "User-agent: bot\nDisallow: /\n";
+
+        assert!(RobotsRules::parse(content, "robot").is_allowed(&url("/")));
+        assert!(RobotsRules::parse(content, "webbot/2.0").is_allowed(&url("/")));
+        assert!(!RobotsRules::parse(content, "Bot-News/1.0").is_allowed(&url("/")));
+        assert!(!RobotsRules::parse(content, "BOT").is_allowed(&url("/")));
+    }
+
+    #[test]
+    fn longest_rule_wins_and_allow_breaks_ties() {
+        let content =
+            "User-agent: *\nDisallow: /docs\nAllow: /docs/public\nAllow: /same\nDisallow: /same\n";
+        let rules = RobotsRules::parse(content, "scraper");
+
+        assert!(!rules.is_allowed(&url("/docs/private")));
+        assert!(rules.is_allowed(&url("/docs/public/page")));
+        assert!(rules.is_allowed(&url("/same")));
+    }
+
+    #[test]
+    fn wildcards_and_end_anchors() {
+        let content = "User-agent: *\nDisallow: /*.pdf$\nDisallow: /search?*q=\n";
+        let rules = RobotsRules::parse(content, "scraper");
+
+        assert!(!rules.is_allowed(&url("/files/book.pdf")));
+        assert!(rules.is_allowed(&url("/files/book.pdf.html")));
+        assert!(!rules.is_allowed(&url("/search?lang=en&q=rust")));
+        assert!(rules.is_allowed(&url("/search")));
+    }
+}
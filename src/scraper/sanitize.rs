@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use unicode_normalization::UnicodeNormalization;
+
+// Leaves room for collision suffixes below the usual 255 bytes limit
+const MAX_COMPONENT_BYTES: usize = 200;
+
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// Turns scraped text into a single file name valid on Linux, macOS and Windows
+pub fn sanitize_component(value: &str) -> String {
+    let normalized = value
+        .nfc()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    // Windows drops trailing dots and spaces
+    let mut name = normalized.trim().trim_end_matches(['.', ' ']).to_string();
+
+    if name.len() > MAX_COMPONENT_BYTES {
+        let mut end = MAX_COMPONENT_BYTES;
+
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        name.truncate(end);
+        name = name.trim_end_matches(['.', ' ']).to_string();
+    }
+
+    let stem = name.split('.').next().unwrap_or_default();
+
+    if RESERVED_NAMES.contains(&stem.to_uppercase().as_str()) {
+        name.insert(0, '_');
+    }
+
+    match name.is_empty() {
+        true => String::from("_"),
+        false => name,
+    }
+}
+
+// Keeps the directories of the path, every segment is sanitized on its own
+pub fn sanitize_path(value: &str) -> String {
+    let segments = value
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != "." && *segment != "..")
+        .map(sanitize_component)
+        .collect::<Vec<_>>();
+
+    match segments.is_empty() {
+        true => String::from("_"),
+        false => segments.join("/"),
+    }
+}
+
+// Different values must not share a name once sanitized, e.g. `A/B` and `A:B`.
+// Names are claimed within a scope, e.g. the titles of a folder, so that values
+// of other scopes cannot shift the suffixes and names stay the same on reruns
+#[derive(Default)]
+pub struct PathSanitizer {
+    // Owner value of each name, by scope
+    claimed: HashMap<String, HashMap<String, String>>,
+}
+
+impl PathSanitizer {
+    pub fn component(&mut self, scope: &str, value: &str) -> String {
+        self.claim(scope, value, sanitize_component(value))
+    }
+
+    pub fn path(&mut self, scope: &str, value: &str) -> String {
+        self.claim(scope, value, sanitize_path(value))
+    }
+
+    // Name for the owner value, e.g. a title shortened to a few characters still
+    // belongs to the full title
+    pub fn claim(&mut self, scope: &str, owner: &str, name: String) -> String {
+        let claimed = self.claimed.entry(String::from(scope)).or_default();
+        let mut candidate = name.clone();
+        let mut suffix = 2;
+
+        loop {
+            match claimed.get(&candidate) {
+                Some(claimer) if claimer != owner => {
+                    candidate = format!("{name} ({suffix})");
+                    suffix += 1;
+                }
+                Some(_) => return candidate,
+                None => {
+                    claimed.insert(candidate.clone(), String::from(owner));
+
+                    return candidate;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn component_replaces_separators_and_reserved_characters() {
+        assert_eq!(sanitize_component("One/Piece: Vol?1"), "One_Piece_ Vol_1");
+        assert_eq!(sanitize_component("tab\there"), "tab_here");
+        assert_eq!(sanitize_component("  ..  "), "_");
+    }
+
+    #[test]
+    fn component_drops_trailing_dots_and_spaces() {
+        assert_eq!(sanitize_component("Chapter 1. "), "Chapter 1");
+        assert_eq!(sanitize_component("end..."), "end");
+    }
+
+    #[test]
+    fn component_escapes_windows_reserved_names() {
+        assert_eq!(sanitize_component("CON"), "_CON");
+        assert_eq!(sanitize_component("com1.txt"), "_com1.txt");
+        assert_eq!(sanitize_component("CONSOLE"), "CONSOLE");
+    }
+
+    #[test]
+    fn component_is_limited_in_bytes_on_char_boundaries() {
+        let name = sanitize_component(&"é".repeat(150));
+
+        assert!(name.len() <= MAX_COMPONENT_BYTES);
+        assert_eq!(name, "é".repeat(MAX_COMPONENT_BYTES / 2));
+    }
+
+    #[test]
+    fn path_keeps_folders_but_not_traversals() {
+        assert_eq!(
+            sanitize_path("/manga/../one:piece/./1/"),
+            "manga/one_piece/1"
+        );
+        assert_eq!(sanitize_path("/"), "_");
+    }
+
+    #[test]
+    fn colliding_values_get_a_suffix_within_their_scope() {
+        let mut sanitizer = PathSanitizer::default();
+
+        assert_eq!(sanitizer.component("title", "A/B"), "A_B");
+        assert_eq!(sanitizer.component("title", "A:B"), "A_B (2)");
+        assert_eq!(sanitizer.component("title", "A/B"), "A_B");
+        assert_eq!(sanitizer.component("other", "A:B"), "A_B");
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use ::image::{imageops::FilterType, DynamicImage};
+use derive_getters::Getters;
+
+use crate::error::{ScrapeError, ScrapeResult};
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum HashAlgorithm {
+    // Pixels brighter than the mean, robust to small color changes
+    Ahash,
+    // Brightness gradient between neighbor pixels, robust to re-encoding
+    #[default]
+    Dhash,
+}
+
+impl Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HashAlgorithm::Ahash => write!(f, "ahash"),
+            HashAlgorithm::Dhash => write!(f, "dhash"),
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ahash" => Ok(Self::Ahash),
+            "dhash" => Ok(Self::Dhash),
+            _ => Err("Unsupported perceptual hash"),
+        }
+    }
+}
+
+// 64 bits hashes of a downscaled grayscale image, similar images have close hashes
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PerceptualHash {
+    ahash: u64,
+    dhash: u64,
+}
+
+impl PerceptualHash {
+    // None when the content cannot be decoded, e.g. SVG images
+    pub fn of(content: &[u8]) -> Option<Self> {
+        let image = ::image::load_from_memory(content).ok()?.grayscale();
+
+        Some(Self {
+            ahash: average_hash(&image),
+            dhash: difference_hash(&image),
+        })
+    }
+
+    // Number of differing bits, from 0 for identical images to 64
+    pub fn distance(&self, other: &PerceptualHash, algorithm: HashAlgorithm) -> u32 {
+        match algorithm {
+            HashAlgorithm::Ahash => (self.ahash ^ other.ahash).count_ones(),
+            HashAlgorithm::Dhash => (self.dhash ^ other.dhash).count_ones(),
+        }
+    }
+}
+
+fn average_hash(image: &DynamicImage) -> u64 {
+    let pixels = image.resize_exact(8, 8, FilterType::Triangle).to_luma8();
+    let mean = pixels.pixels().map(|p| p.0[0] as u64).sum::<u64>() / 64;
+
+    pixels
+        .pixels()
+        .fold(0, |hash, p| (hash << 1) | (p.0[0] as u64 > mean) as u64)
+}
+
+fn difference_hash(image: &DynamicImage) -> u64 {
+    let pixels = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0;
+
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = pixels.get_pixel(x, y).0[0];
+            let right = pixels.get_pixel(x + 1, y).0[0];
+
+            hash = (hash << 1) | (left > right) as u64;
+        }
+    }
+
+    hash
+}
+
+// Known unwanted images, such as watermarks or ads inserted on every page
+#[derive(Default, Debug)]
+pub struct ImageBlacklist {
+    entries: Vec<(PathBuf, PerceptualHash)>,
+}
+
+impl ImageBlacklist {
+    // Files of the directory that cannot be decoded are ignored with a warning
+    pub fn load(dir: &str) -> ScrapeResult<Self> {
+        let entries = fs::read_dir(dir).map_err(|err| {
+            ScrapeError::ConfigError(format!("Unable to read blacklist directory {dir}: {err}"))
+        })?;
+        let mut blacklist = Self::default();
+
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if !path.is_file() {
+                continue;
+            }
+
+            match fs::read(&path)
+                .ok()
+                .and_then(|content| PerceptualHash::of(&content))
+            {
+                Some(hash) => blacklist.entries.push((path, hash)),
+                None => println!(
+                    "Ignoring {} in blacklist, not a decodable image",
+                    path.display()
+                ),
+            }
+        }
+
+        Ok(blacklist)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Blacklisted image the hash is close enough to
+    pub fn find(
+        &self,
+        hash: &PerceptualHash,
+        algorithm: HashAlgorithm,
+        threshold: u32,
+    ) -> Option<&Path> {
+        self.entries
+            .iter()
+            .find(|(_, blacklisted)| hash.distance(blacklisted, algorithm) <= threshold)
+            .map(|(path, _)| path.as_path())
+    }
+}
+
+// Perceptual hash settings shared by the blacklist and the near-duplicate check
+#[derive(Getters, Clone, Debug)]
+pub struct SimilarityFilter {
+    algorithm: HashAlgorithm,
+    // Max number of differing bits out of 64 for two images to be similar
+    threshold: u32,
+    blacklist: Arc<ImageBlacklist>,
+    // Skip images similar to one already downloaded in the run
+    skip_similar: bool,
+}
+
+impl Default for SimilarityFilter {
+    fn default() -> Self {
+        Self {
+            algorithm: HashAlgorithm::default(),
+            threshold: 5,
+            blacklist: Arc::new(ImageBlacklist::default()),
+            skip_similar: false,
+        }
+    }
+}
+
+impl SimilarityFilter {
+    pub fn set_algorithm(&mut self, algorithm: HashAlgorithm) -> &mut Self {
+        self.algorithm = algorithm;
+
+        self
+    }
+
+    pub fn set_threshold(&mut self, threshold: u32) -> &mut Self {
+        self.threshold = threshold.min(64);
+
+        self
+    }
+
+    pub fn set_blacklist(&mut self, blacklist: ImageBlacklist) -> &mut Self {
+        self.blacklist = Arc::new(blacklist);
+
+        self
+    }
+
+    pub fn set_skip_similar(&mut self, skip_similar: bool) -> &mut Self {
+        self.skip_similar = skip_similar;
+
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.blacklist.is_empty() || self.skip_similar
+    }
+
+    pub fn is_similar(&self, a: &PerceptualHash, b: &PerceptualHash) -> bool {
+        a.distance(b, self.algorithm) <= self.threshold
+    }
+
+    pub fn blacklisted(&self, hash: &PerceptualHash) -> Option<&Path> {
+        self.blacklist.find(hash, self.algorithm, self.threshold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io::Cursor};
+
+    use ::image::{ImageFormat, Rgb, RgbImage};
+
+    use super::*;
+
+    // Horizontal gradient, darker to the right unless mirrored
+    fn gradient(mirrored: bool, format: ImageFormat) -> Vec<u8> {
+        let mut content = Cursor::new(vec![]);
+
+        RgbImage::from_fn(64, 48, |x, y| {
+            let x = if mirrored { x } else { 63 - x };
+            let value = (x * 4 + y / 8) as u8;
+
+            Rgb([value, value / 2, 255 - value])
+        })
+        .write_to(&mut content, format)
+        .unwrap();
+
+        content.into_inner()
+    }
+
+    #[test]
+    fn reencoded_images_stay_under_the_threshold() {
+        let filter = SimilarityFilter::default();
+        let original = PerceptualHash::of(&gradient(false, ImageFormat::Png)).unwrap();
+        let reencoded = PerceptualHash::of(&gradient(false, ImageFormat::Jpeg)).unwrap();
+
+        for algorithm in [HashAlgorithm::Ahash, HashAlgorithm::Dhash] {
+            assert!(original.distance(&reencoded, algorithm) <= *filter.threshold());
+        }
+
+        assert!(filter.is_similar(&original, &reencoded));
+    }
+
+    #[test]
+    fn different_images_exceed_the_threshold() {
+        let filter = SimilarityFilter::default();
+        let original = PerceptualHash::of(&gradient(false, ImageFormat::Png)).unwrap();
+        let mirrored = PerceptualHash::of(&gradient(true, ImageFormat::Png)).unwrap();
+
+        for algorithm in [HashAlgorithm::Ahash, HashAlgorithm::Dhash] {
+            assert!(original.distance(&mirrored, algorithm) > *filter.threshold());
+        }
+
+        assert!(!filter.is_similar(&original, &mirrored));
+        assert_eq!(PerceptualHash::of(b"<svg/>"), None);
+    }
+
+    #[test]
+    fn blacklist_finds_close_images_and_ignores_undecodable_files() {
+        let dir = env::temp_dir().join(format!("scraper-blacklist-{}", std::process::id()));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("ad.png"), gradient(false, ImageFormat::Png)).unwrap();
+        fs::write(dir.join("notes.txt"), b"not an image").unwrap();
+
+        let mut filter = SimilarityFilter::default();
+
+        filter.set_blacklist(ImageBlacklist::load(&dir.to_string_lossy()).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+
+        let ad = PerceptualHash::of(&gradient(false, ImageFormat::Jpeg)).unwrap();
+        let page = PerceptualHash::of(&gradient(true, ImageFormat::Png)).unwrap();
+
+        assert!(filter.is_enabled());
+        assert_eq!(filter.blacklisted(&ad), Some(dir.join("ad.png").as_path()));
+        assert_eq!(filter.blacklisted(&page), None);
+    }
+}
@@ -1,14 +1,17 @@
-use std::{collections::HashMap, fmt::Display, str::FromStr};
+use std::{collections::HashMap, fmt::Display, path::Path, str::FromStr, time::SystemTime};
 
 use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use derive_getters::Getters;
 use regex::Regex;
-use thirtyfour::By;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
 use tokio::sync::mpsc::Sender;
 use url::Url;
 
-use crate::error::ScrapeResult;
+use crate::error::{ScrapeError, ScrapeResult};
 
-use super::{new_driver, Scrape};
+use super::{new_engine, Engine, Scrape, ScrapeContext, ScrapeMessage};
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum UrlTag {
@@ -49,6 +52,12 @@ impl FromStr for UrlTag {
     }
 }
 
+impl Serialize for UrlTag {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
 impl UrlTag {
     fn source_attr(&self) -> String {
         let attr = match self {
@@ -64,6 +73,148 @@ impl UrlTag {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum UrlOutputFormat {
+    // One bare url per line
+    #[default]
+    Txt,
+    Jsonl,
+    Csv,
+}
+
+impl Display for UrlOutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UrlOutputFormat::Txt => write!(f, "txt"),
+            UrlOutputFormat::Jsonl => write!(f, "jsonl"),
+            UrlOutputFormat::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+impl FromStr for UrlOutputFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "txt" => Ok(Self::Txt),
+            "jsonl" => Ok(Self::Jsonl),
+            "csv" => Ok(Self::Csv),
+            _ => Err("Unsupported url output format"),
+        }
+    }
+}
+
+impl UrlOutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            UrlOutputFormat::Txt => "txt",
+            UrlOutputFormat::Jsonl => "jsonl",
+            UrlOutputFormat::Csv => "csv",
+        }
+    }
+
+    // Format of a url file written by a previous run, guessed from its extension
+    pub fn from_path(path: &Path) -> Self {
+        path.extension()
+            .and_then(|ext| Self::from_str(&ext.to_string_lossy()).ok())
+            .unwrap_or_default()
+    }
+
+    // Url of a line of a file in this format, None for headers, comments and broken lines
+    pub fn parse_url(&self, line: &str) -> Option<String> {
+        match self {
+            UrlOutputFormat::Txt => {
+                let line = line.trim();
+
+                // Blank lines and `#` comments let url lists be annotated
+                match line.is_empty() || line.starts_with('#') {
+                    true => None,
+                    false => Some(String::from(line)),
+                }
+            }
+            UrlOutputFormat::Jsonl => serde_json::from_str::<Value>(line)
+                .ok()?
+                .get("url")?
+                .as_str()
+                .map(String::from),
+            UrlOutputFormat::Csv => {
+                let url = match line.strip_prefix('"') {
+                    Some(quoted) => quoted.split("\",").next()?.replace("\"\"", "\""),
+                    None => String::from(line.split(',').next()?),
+                };
+
+                match url == ScrapedUrl::CSV_COLUMNS[0] {
+                    true => None,
+                    false => Some(url),
+                }
+            }
+        }
+    }
+
+    // First line of the file, only CSV has one
+    pub fn header(&self) -> Option<String> {
+        match self {
+            UrlOutputFormat::Csv => Some(ScrapedUrl::CSV_COLUMNS.join(",")),
+            _ => None,
+        }
+    }
+}
+
+// Quotes a CSV field when it contains a separator, a quote or a line break
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => String::from(value),
+    }
+}
+
+// A url found on a page, with where and how it was found
+#[derive(Clone, Debug, Getters, Serialize)]
+pub struct ScrapedUrl {
+    url: String,
+    // Page the url was found on
+    source: String,
+    tag: UrlTag,
+    attribute: String,
+    text: String,
+    // Depth of the source page, start urls are at 0
+    depth: usize,
+    timestamp: String,
+}
+
+impl ScrapedUrl {
+    const CSV_COLUMNS: [&'static str; 7] = [
+        "url",
+        "source",
+        "tag",
+        "attribute",
+        "text",
+        "depth",
+        "timestamp",
+    ];
+
+    pub fn format(&self, format: UrlOutputFormat) -> String {
+        match format {
+            UrlOutputFormat::Txt => self.url.clone(),
+            UrlOutputFormat::Jsonl => serde_json::to_string(self).unwrap_or_default(),
+            UrlOutputFormat::Csv => [
+                self.url.clone(),
+                self.source.clone(),
+                self.tag.to_string(),
+                self.attribute.clone(),
+                self.text.clone(),
+                self.depth.to_string(),
+                self.timestamp.clone(),
+            ]
+            .iter()
+            .map(|value| csv_field(value))
+            .collect::<Vec<_>>()
+            .join(","),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ScrapeUrlFilter {
     tags: Vec<UrlTag>,
@@ -95,20 +246,16 @@ impl ScrapeUrlFilter {
     }
 
     pub fn remove_tag(&mut self, tag: UrlTag) -> &mut Self {
-        match self.tags.iter().position(|&t| t == tag) {
-            Some(removed_index) => {
-                self.tags.remove(removed_index);
-            }
-            None => {}
-        };
+        if let Some(removed_index) = self.tags.iter().position(|&t| t == tag) {
+            self.tags.remove(removed_index);
+        }
 
         self
     }
 
     pub fn set_regex(&mut self, rule: String) -> &mut Self {
-        match Regex::new(&rule) {
-            Ok(regex) => self.regex = regex,
-            Err(_) => {}
+        if let Ok(regex) = Regex::new(&rule) {
+            self.regex = regex;
         }
 
         self
@@ -116,16 +263,22 @@ impl ScrapeUrlFilter {
 }
 
 pub struct UrlScraper {
-    tx: Sender<String>,
+    tx: Sender<ScrapeMessage<ScrapedUrl>>,
     filter: ScrapeUrlFilter,
+    context: ScrapeContext,
     url_counter: HashMap<String, usize>,
 }
 
 impl UrlScraper {
-    pub fn new(tx: Sender<String>, filter: ScrapeUrlFilter) -> Self {
+    pub fn new(
+        tx: Sender<ScrapeMessage<ScrapedUrl>>,
+        filter: ScrapeUrlFilter,
+        context: ScrapeContext,
+    ) -> Self {
         Self {
             tx,
             filter,
+            context,
             url_counter: HashMap::new(),
         }
     }
@@ -133,22 +286,27 @@ impl UrlScraper {
     pub fn is_url(url: &str) -> bool {
         let regex = Regex::new(r"^(?:http(s)?://)[\w.-]+(?:\.[\w\.-]+)+(.?)*$").unwrap();
 
-        regex.is_match(&url)
+        regex.is_match(url)
     }
 
     pub fn path_to_url(&self, url: &Url, path: String) -> String {
-        match path.starts_with("/") {
-            true => url.join(format!("{path}").as_str()).unwrap().to_string(),
-            false => format!("{}/{}", url.host_str().to_owned().unwrap(), path),
+        // Relative paths are resolved against the page they were found on
+        match url.join(&path) {
+            Ok(joined) => joined.to_string(),
+            Err(_) => path,
         }
     }
 
     fn is_matched(&self, url_str: &str) -> bool {
-        self.filter.regex.is_match(&url_str)
+        self.filter.regex.is_match(url_str)
     }
 
     fn is_duplicate(&self, url_str: &str) -> bool {
-        self.url_counter.get(url_str).is_some()
+        self.url_counter.contains_key(url_str)
+    }
+
+    fn is_followable(url_str: &str) -> bool {
+        url_str.starts_with("http://") || url_str.starts_with("https://")
     }
 
     fn count_scraped_url(&mut self, url_str: &str) {
@@ -160,41 +318,232 @@ impl UrlScraper {
     fn is_valid(&self, url_str: &str) -> bool {
         self.is_matched(url_str) && !self.is_duplicate(url_str)
     }
+
+    async fn send(&self, message: ScrapeMessage<ScrapedUrl>) -> ScrapeResult<()> {
+        self.tx
+            .send(message)
+            .await
+            .map_err(|_| ScrapeError::ChannelClosed)
+    }
+
+    async fn scrape_page(
+        &mut self,
+        engine: &mut dyn Engine,
+        url: &str,
+        depth: usize,
+    ) -> ScrapeResult<()> {
+        let max_depth = *self.context.strategy().depth();
+
+        self.context.goto(engine, url).await?;
+
+        // Relative links are resolved against the page reached after redirects
+        let page_url = engine.current_url().await?;
+
+        for tag_name in self.filter.tags.clone() {
+            let source_attr = tag_name.source_attr();
+            let tags = engine
+                .find_all(&tag_name.to_string(), &[&source_attr])
+                .await?;
+
+            for tag in tags {
+                let attr_value = match tag.attr(&source_attr) {
+                    Some(attr_value) => attr_value,
+                    None => continue,
+                };
+                let scraped_url = if Self::is_url(attr_value) {
+                    attr_value.clone()
+                } else {
+                    self.path_to_url(&page_url, attr_value.clone())
+                };
+
+                if !self.is_valid(&scraped_url) {
+                    continue;
+                }
+
+                self.count_scraped_url(&scraped_url);
+
+                // Disallowed urls are neither followed nor written to the output
+                if Self::is_followable(&scraped_url) && !self.context.is_allowed(&scraped_url).await
+                {
+                    continue;
+                }
+
+                if depth < max_depth && Self::is_followable(&scraped_url) {
+                    self.context.queue().push(scraped_url.clone(), depth + 1);
+                }
+
+                if !self.context.journal().is_saved(&scraped_url) {
+                    let now: DateTime<Utc> = SystemTime::now().into();
+
+                    self.send(ScrapeMessage::Data(ScrapedUrl {
+                        url: scraped_url,
+                        source: String::from(url),
+                        tag: tag_name,
+                        attribute: source_attr.clone(),
+                        text: tag.text().clone(),
+                        depth,
+                        timestamp: now.to_rfc3339_opts(SecondsFormat::Millis, true),
+                    }))
+                    .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl Scrape for UrlScraper {
-    async fn scrape(&mut self, urls: &Vec<String>) -> ScrapeResult<()> {
-        let driver = new_driver().await?;
-
-        for url in urls {
-            driver.goto(url).await.unwrap();
-
-            if let Ok(parsed_url) = Url::parse(url) {
-                for tag_name in self.filter.tags.clone() {
-                    let tags = driver
-                        .find_all(By::Tag(&tag_name.to_string()))
-                        .await
-                        .unwrap();
-
-                    for tag in tags {
-                        if let Some(attr_value) = tag.attr(&tag_name.source_attr()).await.unwrap() {
-                            let scraped_url = if Self::is_url(&attr_value) {
-                                attr_value
-                            } else {
-                                self.path_to_url(&parsed_url, attr_value)
-                            };
-
-                            if self.is_valid(&scraped_url) {
-                                self.count_scraped_url(&scraped_url);
-                                self.tx.send(scraped_url).await.unwrap();
-                            }
-                        }
-                    }
-                }
+    async fn scrape(&mut self) -> ScrapeResult<()> {
+        let mut engine = new_engine(&self.context).await?;
+        let max_depth = *self.context.strategy().depth();
+        let queue = self.context.queue().clone();
+
+        while let Some(job) = queue.pop(self.context.limiter()).await {
+            let (url, depth) = (job.url(), *job.depth());
+
+            // Pages above the depth limit are revisited on resume to rediscover their links
+            if depth >= max_depth && self.context.journal().is_visited(url) {
+                continue;
+            }
+
+            if !self.context.is_allowed(url).await {
+                println!("Skipping {url}, disallowed by robots.txt");
+                continue;
+            }
+
+            match self.scrape_page(engine.as_mut(), url, depth).await {
+                Ok(_) => self.send(ScrapeMessage::PageDone(url.clone())).await?,
+                Err(ScrapeError::ChannelClosed) => return Err(ScrapeError::ChannelClosed),
+                Err(err) => self.context.report_failure(url, err),
             }
         }
 
-        Ok(driver.quit().await.unwrap())
+        engine.quit().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::mpsc::channel;
+
+    use super::*;
+    use crate::scraper::{
+        tests::{serve, Route},
+        CrawlJournal, EngineKind, ScrapeStrategy,
+    };
+
+    // Urls sent by a crawl of the start url, in page order
+    async fn crawl(start: String, strategy: ScrapeStrategy) -> Vec<ScrapedUrl> {
+        let (tx, mut rx) = channel(10);
+        let context =
+            ScrapeContext::new(strategy, Arc::new(CrawlJournal::default()), None).unwrap();
+
+        context.queue().push(start, 0);
+        UrlScraper::new(tx, ScrapeUrlFilter::default(), context)
+            .scrape()
+            .await
+            .unwrap();
+
+        let mut urls = vec![];
+
+        while let Some(message) = rx.recv().await {
+            if let ScrapeMessage::Data(url) = message {
+                urls.push(url);
+            }
+        }
+
+        urls
+    }
+
+    #[tokio::test]
+    async fn links_resolve_against_the_page_after_redirects() {
+        let page = r#"<a href="2">Next</a> <a href="../index">Index</a>"#;
+        let (base, _) = serve(vec![
+            ("/start", Route::Redirect("/chapter/1")),
+            ("/chapter/1", Route::Content("text/html", page.into())),
+        ])
+        .await;
+        let mut strategy = ScrapeStrategy::default();
+
+        strategy.set_engine(EngineKind::Http);
+
+        let urls = crawl(format!("{base}/start"), strategy).await;
+
+        assert_eq!(
+            urls.iter().map(|url| url.url().clone()).collect::<Vec<_>>(),
+            vec![format!("{base}/chapter/2"), format!("{base}/index")]
+        );
+        assert_eq!(urls[0].source(), &format!("{base}/start"));
+    }
+
+    #[tokio::test]
+    async fn links_disallowed_by_robots_are_dropped() {
+        let page = r#"<a href="/private/1">Private</a> <a href="/public/1">Public</a>"#;
+        let (base, _) = serve(vec![
+            ("/start", Route::Content("text/html", page.into())),
+            (
+                "/robots.txt",
+                Route::Content(
+                    "text/plain",
+                    b"User-agent: *\nDisallow: /private\n".to_vec(),
+                ),
+            ),
+        ])
+        .await;
+        let mut strategy = ScrapeStrategy::default();
+
+        strategy
+            .set_engine(EngineKind::Http)
+            .set_respect_robots(true);
+
+        let urls = crawl(format!("{base}/start"), strategy).await;
+
+        assert_eq!(
+            urls.iter().map(|url| url.url().clone()).collect::<Vec<_>>(),
+            vec![format!("{base}/public/1")]
+        );
+    }
+
+    #[test]
+    fn txt_lines_skip_blanks_and_comments() {
+        let format = UrlOutputFormat::Txt;
+
+        assert_eq!(
+            format.parse_url("  https://example.com/a  "),
+            Some(String::from("https://example.com/a"))
+        );
+        assert_eq!(format.parse_url(""), None);
+        assert_eq!(format.parse_url("# chapters"), None);
+    }
+
+    #[test]
+    fn jsonl_lines_read_the_url_field() {
+        let format = UrlOutputFormat::Jsonl;
+
+        assert_eq!(
+            format.parse_url(r#"{"url":"https://example.com/a","depth":1}"#),
+            Some(String::from("https://example.com/a"))
+        );
+        assert_eq!(format.parse_url(r#"{"source":"x"}"#), None);
+        assert_eq!(format.parse_url("not json"), None);
+    }
+
+    #[test]
+    fn csv_lines_read_the_first_column() {
+        let format = UrlOutputFormat::Csv;
+
+        assert_eq!(format.parse_url(&format.header().unwrap()), None);
+        assert_eq!(
+            format.parse_url("https://example.com/a,https://example.com,a,href,,0,"),
+            Some(String::from("https://example.com/a"))
+        );
+        assert_eq!(
+            format.parse_url(r#""https://example.com/?q=a,""b""",https://example.com"#),
+            Some(String::from(r#"https://example.com/?q=a,"b""#))
+        );
     }
 }
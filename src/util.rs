@@ -1,6 +1,7 @@
+#[deprecated(note = "urls are no longer split into batches, workers pull from scraper::WorkQueue")]
 pub fn get_batch_range(total: usize, batches: usize, order: usize) -> Option<(usize, usize)> {
     // Round up batch_size
-    let batch_size = if total % batches == 0 {
+    let batch_size = if total.is_multiple_of(batches) {
         total / batches
     } else {
         total / batches + 1